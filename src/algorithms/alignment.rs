@@ -0,0 +1,325 @@
+use rand::RngCore;
+
+use crate::geometry::{Rect, Size};
+
+use crate::{ObstaclePackingAlgorithm, OnlinePacker, PackingAlgorithm, PackingError};
+
+use super::obstacles::ObstructedPackingAlgorithm;
+use super::packing::beam::corner_points;
+
+/// Places the rectangles packed by `packer` at positions whose coordinates are multiples of
+/// `alignment`, by packing on a grid of `alignment` sized cells.
+///
+/// Each rectangle is rounded up to a whole number of cells, and the packer works in units of
+/// cells, so a packer with a fixed width or bounds should be given the container's size divided
+/// by the alignment, rounded down.
+pub struct AlignedPackingAlgorithm<P: PackingAlgorithm> {
+    pub packer: P,
+    pub alignment: i32,
+    /// The container that rectangles must stay within when they are moved to aligned positions.
+    /// If `None`, they are kept within the width that they were packed into.
+    pub bounds: Option<Rect>,
+}
+
+/// Places the rectangles placed by `packer` at aligned positions, the same way as
+/// [AlignedPackingAlgorithm].
+pub struct AlignedOnlinePacker<O: OnlinePacker> {
+    pub packer: O,
+    pub alignment: i32,
+}
+
+impl<P: PackingAlgorithm> PackingAlgorithm for AlignedPackingAlgorithm<P> {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.pack_aligned(rects, &[], rng)
+    }
+}
+
+/// The obstacles are grown to the cells they cover, so that the rectangles are packed and moved
+/// around them.
+impl<P: ObstaclePackingAlgorithm> ObstaclePackingAlgorithm for AlignedPackingAlgorithm<P> {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let grid = self.alignment.max(1);
+        let obstacles = obstacles
+            .iter()
+            .map(|o| Rect {
+                x1: o.x1.div_euclid(grid),
+                y1: o.y1.div_euclid(grid),
+                x2: round_up(o.x2, grid) / grid,
+                y2: round_up(o.y2, grid) / grid,
+            })
+            .collect::<Vec<_>>();
+        let obstructed = AlignedPackingAlgorithm {
+            packer: ObstructedPackingAlgorithm {
+                packer: &self.packer,
+                obstacles: obstacles.clone(),
+            },
+            alignment: self.alignment,
+            bounds: self.bounds.clone(),
+        };
+
+        obstructed.align(rects, &[], &obstacles, rng)
+    }
+}
+
+impl<P: PackingAlgorithm> AlignedPackingAlgorithm<P> {
+    /// Packs the `rects` like [PackingAlgorithm::pack], additionally aligning each rectangle to
+    /// the corresponding entry of `alignments`, if there is one.
+    ///
+    /// Only the rectangles' positions are aligned, not their sizes: after packing, each one is
+    /// moved right and down to the nearest position aligned to its own alignment, or to the lowest
+    /// free aligned position if that overlaps another rectangle. Alignments that are multiples of
+    /// `alignment` waste the least space. The rectangles keep the order given, as with
+    /// [PackingAlgorithm::pack_in_order].
+    ///
+    /// Fails with [PackingError::Unplaceable] if no free aligned position within the `bounds` is
+    /// left for some rectangle.
+    pub fn pack_aligned(
+        &self,
+        rects: &mut [Rect],
+        alignments: &[i32],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        self.align(rects, alignments, &[], rng)
+    }
+
+    /// Like [AlignedPackingAlgorithm::pack_aligned], but also moves the rectangles around the
+    /// `obstacles`, which are given in cells.
+    fn align(
+        &self,
+        rects: &mut [Rect],
+        alignments: &[i32],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let grid = self.alignment.max(1);
+        // the alignment of each rectangle in cells
+        let steps = (0..rects.len())
+            .map(|i| lcm(grid, alignments.get(i).copied().unwrap_or(1)) / grid)
+            .collect::<Vec<_>>();
+
+        let items = rects.to_vec();
+        let cells = items
+            .iter()
+            .map(|rect| to_cells(&rect.size(), grid))
+            .collect::<Vec<_>>();
+
+        let mut placed = cells.clone();
+        let attempts = self.packer.pack_in_order(&mut placed, rng)?;
+        let bounds = self.bounds.as_ref().map(|b| Rect {
+            x1: round_up(b.x1, grid) / grid,
+            y1: round_up(b.y1, grid) / grid,
+            x2: b.x2.div_euclid(grid),
+            y2: b.y2.div_euclid(grid),
+        });
+        snap(&mut placed, &steps, bounds.as_ref(), obstacles)?;
+
+        for (i, rect) in rects.iter_mut().enumerate() {
+            *rect = from_cells(&placed[i], &cells[i], &items[i].size(), grid);
+        }
+
+        Ok(attempts)
+    }
+}
+
+impl<O: OnlinePacker> OnlinePacker for AlignedOnlinePacker<O> {
+    fn place(&mut self, size: &Size) -> Option<Rect> {
+        let grid = self.alignment.max(1);
+        let cells = to_cells(size, grid);
+
+        let placed = self.packer.place(&cells.size())?;
+
+        Some(from_cells(&placed, &cells, size, grid))
+    }
+}
+
+/// Returns the rectangle covering the cells that a rectangle of the given `size` takes up.
+fn to_cells(size: &Size, grid: i32) -> Rect {
+    Rect {
+        x1: 0,
+        y1: 0,
+        x2: (size.width + grid - 1) / grid,
+        y2: (size.height + grid - 1) / grid,
+    }
+}
+
+/// Moves each of the `placed` cells so that its coordinates are multiples of its entry of
+/// `steps`, keeping them within `bounds` and clear of the `obstacles`, all given in cells. Without
+/// `bounds`, they are kept within the width that they were packed into.
+///
+/// Rectangles are moved from the top left, each to the nearest aligned position right of and
+/// below it, or if that doesn't fit, to the free aligned corner point with the lowest `y2` edge.
+/// Fails with [PackingError::Unplaceable] if there is no such point.
+fn snap(
+    placed: &mut [Rect],
+    steps: &[i32],
+    bounds: Option<&Rect>,
+    obstacles: &[Rect],
+) -> Result<(), PackingError> {
+    let width = placed.iter().map(|r| r.x2).max().unwrap_or(0);
+    let origin = bounds.map_or((0, 0), |b| (b.x1, b.y1));
+
+    let mut order = (0..placed.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| (placed[i].y1, placed[i].x1));
+
+    let mut fixed = obstacles.to_vec();
+    for i in order {
+        let (step, rect) = (steps[i], &placed[i]);
+        let at = |(x, y): (i32, i32)| {
+            let (x, y) = (round_up(x, step), round_up(y, step));
+            Rect {
+                x1: x,
+                y1: y,
+                x2: x + rect.width(),
+                y2: y + rect.height(),
+            }
+        };
+        let within = |r: &Rect| match bounds {
+            Some(bounds) => bounds.contains(r),
+            None => r.x2 <= width,
+        };
+        let fits = |r: &Rect| within(r) && fixed.iter().all(|f| !f.overlaps(r));
+
+        let mut snapped = at((rect.x1, rect.y1));
+        if !fits(&snapped) {
+            snapped = corner_points(&fixed, origin)
+                .into_iter()
+                .map(at)
+                .filter(fits)
+                .min_by_key(|r| (r.y2, r.x1))
+                .ok_or(PackingError::Unplaceable { item: i })?;
+        }
+
+        placed[i] = snapped.clone();
+        fixed.push(snapped);
+    }
+
+    Ok(())
+}
+
+/// Rounds `value` up to a multiple of `step`.
+fn round_up(value: i32, step: i32) -> i32 {
+    (value + step - 1).div_euclid(step) * step
+}
+
+/// Places a rectangle of the given `size` at the corner of the `placed` cells, where `cells` is
+/// how they were given to the packer, turning it if the packer turned them.
+fn from_cells(placed: &Rect, cells: &Rect, size: &Size, grid: i32) -> Rect {
+    let size = if placed.width() != cells.width() {
+        size.rotated()
+    } else {
+        size.clone()
+    };
+
+    Rect {
+        x1: placed.x1 * grid,
+        y1: placed.y1 * grid,
+        x2: placed.x1 * grid + size.width,
+        y2: placed.y1 * grid + size.height,
+    }
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn lcm(a: i32, b: i32) -> i32 {
+    let b = b.max(1);
+    a / gcd(a, b) * b
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::algorithms::packing::skyline::{SkylinePackingAlgorithm, SkylineRule};
+
+    use super::*;
+
+    #[test]
+    fn coarse_alignment_only_affects_its_own_rectangle() {
+        let algorithm = AlignedPackingAlgorithm {
+            packer: SkylinePackingAlgorithm {
+                width: None,
+                rule: SkylineRule::BottomLeft,
+                allow_rotation: false,
+            },
+            alignment: 1,
+            bounds: None,
+        };
+
+        let square = Rect {
+            x1: 0,
+            y1: 0,
+            x2: 4,
+            y2: 4,
+        };
+        let mut rects = vec![square; 20];
+        let mut alignments = vec![1; 20];
+        alignments[7] = 64;
+
+        algorithm
+            .pack_aligned(&mut rects, &alignments, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+
+        for (i, rect) in rects.iter().enumerate() {
+            assert_eq!((rect.width(), rect.height()), (4, 4));
+            assert!(rect.x1 % alignments[i] == 0 && rect.y1 % alignments[i] == 0);
+            assert!(rects[..i].iter().all(|r| !r.overlaps(rect)));
+        }
+
+        // the 64 aligned rectangle is only moved, so it reaches at most 64 + 4 along one axis
+        // while the others stay in a narrow strip, instead of taking up 64 by 64 itself
+        let bbox = Rect::bbox(rects.iter());
+        assert!(bbox.area() <= (64 + 4) * 20, "{bbox:?}");
+    }
+
+    #[test]
+    fn snapping_stays_within_bounds() {
+        let square = Rect {
+            x1: 0,
+            y1: 0,
+            x2: 2,
+            y2: 2,
+        };
+
+        // the squares are placed at multiples of 3, three side by side
+        for (height, fits) in [(4, false), (5, true)] {
+            let algorithm = AlignedPackingAlgorithm {
+                packer: SkylinePackingAlgorithm {
+                    width: Some(8),
+                    rule: SkylineRule::BottomLeft,
+                    allow_rotation: false,
+                },
+                alignment: 1,
+                bounds: Some(Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: 8,
+                    y2: height,
+                }),
+            };
+
+            let mut rects = vec![square.clone(); 4];
+            let result = algorithm.pack_aligned(&mut rects, &[3; 4], &mut StdRng::seed_from_u64(0));
+
+            if fits {
+                assert!(result.is_ok());
+                assert!(rects
+                    .iter()
+                    .all(|r| r.x1 % 3 == 0 && r.y1 % 3 == 0 && r.y2 <= height));
+            } else {
+                assert!(matches!(result, Err(PackingError::Unplaceable { .. })));
+            }
+        }
+    }
+}
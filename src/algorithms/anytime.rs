@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Limits how long an [AnytimePackingAlgorithm](crate::AnytimePackingAlgorithm) searches for.
+/// The search stops as soon as any of the limits is reached, or when the packer would have
+/// finished anyway, so the default budget changes nothing.
+#[derive(Clone, Debug, Default)]
+pub struct Budget {
+    pub deadline: Option<Instant>,
+    /// The most iterations to run, where what counts as an iteration depends on the packer.
+    pub iterations: Option<usize>,
+    pub cancellation: Option<CancellationToken>,
+}
+
+/// Lets another thread stop a search early. Clones share the same state, so cancelling any of
+/// them cancels them all.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+/// The outcome of an anytime search.
+#[derive(Clone, Debug)]
+pub struct AnytimePacking<S> {
+    /// The score of each packing that improved on the best one so far, with how long after the
+    /// start it was found. The last entry is the score of the packing that was returned.
+    pub timeline: Vec<(Duration, S)>,
+    /// The number of iterations that were run.
+    pub iterations: usize,
+}
+
+impl Budget {
+    /// Returns a budget that runs out after `duration` from now.
+    pub fn within(duration: Duration) -> Budget {
+        Budget {
+            deadline: Some(Instant::now() + duration),
+            ..Budget::default()
+        }
+    }
+
+    /// Returns true if a search that has run `iterations` iterations should stop.
+    pub fn is_exhausted(&self, iterations: usize) -> bool {
+        matches!(self.iterations, Some(limit) if iterations >= limit)
+            || matches!(self.deadline, Some(deadline) if Instant::now() >= deadline)
+            || matches!(&self.cancellation, Some(token) if token.is_cancelled())
+    }
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::algorithms::packing::annealing::{AnnealingPackingAlgorithm, CoolingSchedule};
+    use crate::algorithms::packing::genetic::{Crossover, GeneticPackingAlgorithm};
+    use crate::algorithms::packing::iterative_random::IterativeRandomPackingAlgorithm;
+    use crate::geometry::Rect;
+    use crate::heuristics::score::{score, ScorePackingHeuristic};
+    use crate::AnytimePackingAlgorithm;
+
+    use super::*;
+
+    /// Checks that the timeline only ever improves and ends with the score of the packing that
+    /// was returned, for searches both cut short and run to the end, and that some full search
+    /// reports more than one improvement.
+    fn check_timeline(algorithm: &impl AnytimePackingAlgorithm<Score = i32>) {
+        let mut improved = false;
+
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let rects = (0..15)
+                .map(|_| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: rng.gen_range(1..=10),
+                    y2: rng.gen_range(1..=10),
+                })
+                .collect::<Vec<_>>();
+
+            for budget in [
+                Budget {
+                    iterations: Some(5),
+                    ..Budget::default()
+                },
+                Budget::default(),
+            ] {
+                let mut packing = rects.clone();
+                let result = algorithm
+                    .pack_anytime(&mut packing, &budget, &mut rng)
+                    .unwrap();
+
+                let (_, last) = result.timeline.last().unwrap();
+                assert_eq!(*last, score(&packing), "seed {seed}");
+                assert!(result.timeline.windows(2).all(|w| w[1].1 < w[0].1));
+
+                improved |= budget.iterations.is_none() && result.timeline.len() > 1;
+            }
+        }
+
+        assert!(improved, "the timeline never recorded an improvement");
+    }
+
+    #[test]
+    fn timeline_ends_with_returned_score() {
+        check_timeline(&AnnealingPackingAlgorithm {
+            heuristic: ScorePackingHeuristic,
+            width: None,
+            iterations: 2000,
+            schedule: CoolingSchedule::Linear { initial: 20.0 },
+            allow_rotation: true,
+        });
+        check_timeline(&GeneticPackingAlgorithm {
+            heuristic: ScorePackingHeuristic,
+            width: None,
+            population_size: 10,
+            generations: 30,
+            crossover: Crossover::Order,
+            mutation_rate: 0.3,
+            elitism: 0,
+            tournament_size: 3,
+            allow_rotation: true,
+        });
+        check_timeline(&IterativeRandomPackingAlgorithm {
+            heuristic: ScorePackingHeuristic,
+            trials_per_iteration: 20,
+            width: None,
+            allow_rotation: true,
+        });
+    }
+}
@@ -0,0 +1,227 @@
+use rand::RngCore;
+
+use crate::geometry::{Rect, Spacing};
+
+use crate::PackingAlgorithm;
+
+/// Assigns rectangles to as few identical bins as possible, packing each bin with `packer`.
+///
+/// Rectangles are assigned from largest to smallest. Each assignment repacks the chosen bin from
+/// scratch, so `packer` should be configured to pack within `bounds` (e.g. a strip as wide as the
+/// bin), and a bin only accepts a rectangle if the repacked result still fits within `bounds`.
+pub struct BinPackingAlgorithm<P: PackingAlgorithm> {
+    /// The region each bin covers.
+    pub bounds: Rect,
+    pub packer: P,
+    pub selection: BinSelection,
+    /// Space to keep between rectangles and from the border of each bin, which a bin's contents
+    /// are checked against. The packer should keep it too, e.g. by being a
+    /// [SpacedPackingAlgorithm](crate::algorithms::spacing::SpacedPackingAlgorithm) with the same
+    /// spacing.
+    pub spacing: Spacing,
+}
+
+/// Decides which of the open bins a rectangle is assigned to.
+#[derive(Debug, Clone, Copy)]
+pub enum BinSelection {
+    /// Use the first bin that the rectangle fits in.
+    FirstFit,
+    /// Use the bin that the rectangle leaves the least free area in.
+    BestFit,
+}
+
+/// The contents of a single bin.
+#[derive(Debug, Clone, Default)]
+pub struct Bin {
+    /// The indices of the rectangles in this bin, in the same order as `placements`.
+    pub items: Vec<usize>,
+    /// Where each rectangle was placed within the bin.
+    pub placements: Vec<Rect>,
+}
+
+/// The result of a [BinPackingAlgorithm].
+#[derive(Debug, Clone)]
+pub struct BinPacking {
+    pub bins: Vec<Bin>,
+    /// The total area of the rectangles divided by the area of a bin, rounded up. No packing can
+    /// use fewer bins than this.
+    pub lower_bound: usize,
+}
+
+impl<P: PackingAlgorithm> BinPackingAlgorithm<P> {
+    /// Assigns each of the `rects` to a bin, returning `None` if some rectangle doesn't fit in an
+    /// empty bin, or if there are rectangles and the bins have no area.
+    pub fn pack_bins(&self, rects: &[Rect], rng: &mut dyn RngCore) -> Option<BinPacking> {
+        if !rects.is_empty() && !self.has_area() {
+            return None;
+        }
+
+        let mut order = (0..rects.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| -rects[i].area());
+
+        let mut bins = Vec::<Bin>::new();
+
+        let bin_area = self.bounds.width() as i64 * self.bounds.height() as i64;
+
+        for i in order {
+            // only bins with enough free area left are worth repacking, and for best fit they're
+            // tried from the least free area up, so the first one that fits is the best
+            let area = rects[i].area() as i64;
+            let free_area = |bin: &Bin| bin_area - used_area(bin) as i64;
+            let mut open = (0..bins.len())
+                .filter(|&b| free_area(&bins[b]) >= area)
+                .collect::<Vec<_>>();
+            if let BinSelection::BestFit = self.selection {
+                open.sort_by_key(|&b| free_area(&bins[b]));
+            }
+
+            let chosen = open.into_iter().find_map(|b| {
+                Some((
+                    b,
+                    add_to_bin(
+                        &self.packer,
+                        &self.bounds,
+                        &self.spacing,
+                        rects,
+                        &bins[b],
+                        i,
+                        rng,
+                    )?,
+                ))
+            });
+
+            match chosen {
+                Some((b, bin)) => bins[b] = bin,
+                None => bins.push(add_to_bin(
+                    &self.packer,
+                    &self.bounds,
+                    &self.spacing,
+                    rects,
+                    &Bin::default(),
+                    i,
+                    rng,
+                )?),
+            }
+        }
+
+        Some(BinPacking {
+            bins,
+            lower_bound: self.lower_bound(rects),
+        })
+    }
+
+    /// Calculates the minimum number of bins that could hold the `rects`, judging by area alone.
+    /// If the bins have no area but the `rects` do, no number of bins is enough, and this is
+    /// `usize::MAX`.
+    pub fn lower_bound(&self, rects: &[Rect]) -> usize {
+        let area = rects.iter().map(|r| r.area() as i64).sum::<i64>();
+        if area == 0 {
+            return 0;
+        } else if !self.has_area() {
+            return usize::MAX;
+        }
+
+        let bin_area = self.bounds.width() as i64 * self.bounds.height() as i64;
+
+        ((area + bin_area - 1) / bin_area) as usize
+    }
+
+    fn has_area(&self) -> bool {
+        self.bounds.width() > 0 && self.bounds.height() > 0
+    }
+}
+
+impl BinPacking {
+    /// Returns the fraction of the area of the bins used that is covered by rectangles, given the
+    /// `bounds` of each bin, or zero if no bins are used.
+    pub fn utilization(&self, bounds: &Rect) -> f64 {
+        if self.bins.is_empty() {
+            return 0.0;
+        }
+
+        let used = self
+            .bins
+            .iter()
+            .map(|bin| used_area(bin) as i64)
+            .sum::<i64>();
+        used as f64
+            / (self.bins.len() as i64 * bounds.width() as i64 * bounds.height() as i64) as f64
+    }
+}
+
+/// Repacks `bin` with rectangle `i` added using `packer`, returning the new contents if they fit
+/// within `bounds` and keep the `spacing`.
+pub(crate) fn add_to_bin(
+    packer: &impl PackingAlgorithm,
+    bounds: &Rect,
+    spacing: &Spacing,
+    rects: &[Rect],
+    bin: &Bin,
+    i: usize,
+    rng: &mut dyn RngCore,
+) -> Option<Bin> {
+    let mut items = bin.items.clone();
+    items.push(i);
+
+    let unplaced = items.iter().map(|&j| rects[j].clone()).collect::<Vec<_>>();
+    let mut placements = unplaced.clone();
+
+    if packer.pack(&mut placements, rng).is_err() || !spacing.is_valid(bounds, &placements, &[]) {
+        return None;
+    }
+
+    // the packer may have reordered the rectangles
+    let items = Rect::match_items(&unplaced, &placements)
+        .into_iter()
+        .map(|j| items.get(j).copied())
+        .collect::<Option<_>>()?;
+
+    Some(Bin { items, placements })
+}
+
+pub(crate) fn used_area(bin: &Bin) -> i32 {
+    bin.placements.iter().map(Rect::area).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::algorithms::packing::skyline::{SkylinePackingAlgorithm, SkylineRule};
+
+    use super::*;
+
+    #[test]
+    fn empty_bins_hold_nothing() {
+        let algorithm = BinPackingAlgorithm {
+            bounds: Rect {
+                x1: 0,
+                y1: 0,
+                x2: 0,
+                y2: 8,
+            },
+            packer: SkylinePackingAlgorithm {
+                width: Some(0),
+                rule: SkylineRule::BottomLeft,
+                allow_rotation: false,
+            },
+            selection: BinSelection::FirstFit,
+            spacing: Spacing::default(),
+        };
+        let rects = [Rect {
+            x1: 0,
+            y1: 0,
+            x2: 2,
+            y2: 2,
+        }];
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(algorithm.lower_bound(&rects), usize::MAX);
+        assert_eq!(algorithm.lower_bound(&[]), 0);
+        assert!(algorithm.pack_bins(&rects, &mut rng).is_none());
+        let packing = algorithm.pack_bins(&[], &mut rng).unwrap();
+        assert!(packing.bins.is_empty());
+        assert_eq!(packing.utilization(&algorithm.bounds), 0.0);
+    }
+}
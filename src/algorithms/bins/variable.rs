@@ -0,0 +1,284 @@
+use std::error::Error;
+use std::fmt;
+
+use rand::RngCore;
+
+use crate::geometry::{Rect, Spacing};
+
+use crate::PackingAlgorithm;
+
+use super::identical::{add_to_bin, used_area, Bin};
+
+/// Chooses which stock sheets to cut and assigns rectangles to them, trying to minimize the total
+/// cost of the sheets used.
+///
+/// Sheets are opened one at a time: every kind of stock still on hand is filled greedily with the
+/// largest remaining rectangles, and the kind with the lowest cost per unit of area filled is
+/// used. Once every rectangle is assigned, each sheet is swapped for the cheapest kind of stock
+/// still on hand that its rectangles also fit in.
+pub struct VariableBinPackingAlgorithm<P: PackingAlgorithm, F: Fn(&Rect) -> P> {
+    pub stock: Vec<Stock>,
+    /// Creates the packer used to pack a sheet with the given bounds, e.g. by setting its width.
+    pub packer: F,
+    /// Space to keep around the rectangles on each sheet, checked like
+    /// [BinPackingAlgorithm::spacing](super::identical::BinPackingAlgorithm::spacing).
+    pub spacing: Spacing,
+}
+
+/// A kind of stock sheet.
+#[derive(Debug, Clone)]
+pub struct Stock {
+    /// The region a sheet of this kind covers.
+    pub bounds: Rect,
+    pub cost: f64,
+    /// The number of sheets of this kind on hand.
+    pub quantity: usize,
+}
+
+/// A stock sheet and the rectangles packed into it.
+#[derive(Debug, Clone)]
+pub struct Sheet {
+    /// The index of the sheet's kind of [Stock].
+    pub stock: usize,
+    pub bin: Bin,
+    /// The fraction of the sheet's area covered by rectangles.
+    pub utilization: f64,
+}
+
+/// The result of a [VariableBinPackingAlgorithm].
+#[derive(Debug, Clone)]
+pub struct StockPacking {
+    pub sheets: Vec<Sheet>,
+    /// The total cost of the sheets used.
+    pub cost: f64,
+    /// The number of sheets of each kind of stock left unused.
+    pub unused: Vec<usize>,
+}
+
+/// Why a [VariableBinPackingAlgorithm] didn't find a packing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StockError {
+    /// The kind of stock with this index covers no area.
+    EmptyStock { stock: usize },
+    /// The rectangle with this index in the input couldn't be placed in any of the stock left on
+    /// hand.
+    Unplaceable { item: usize },
+}
+
+impl fmt::Display for StockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StockError::EmptyStock { stock } => write!(f, "stock {stock} has no area"),
+            StockError::Unplaceable { item } => {
+                write!(f, "couldn't place rectangle {item} in the stock on hand")
+            }
+        }
+    }
+}
+
+impl Error for StockError {}
+
+impl<P: PackingAlgorithm, F: Fn(&Rect) -> P> VariableBinPackingAlgorithm<P, F> {
+    /// Assigns each of the `rects` to a stock sheet, failing if some kind of stock has no area or
+    /// the rectangles can't all be packed into the stock on hand.
+    pub fn pack_stock(
+        &self,
+        rects: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<StockPacking, StockError> {
+        if let Some(stock) = self
+            .stock
+            .iter()
+            .position(|s| s.bounds.width() <= 0 || s.bounds.height() <= 0)
+        {
+            return Err(StockError::EmptyStock { stock });
+        }
+
+        let packers = self
+            .stock
+            .iter()
+            .map(|stock| (self.packer)(&stock.bounds))
+            .collect::<Vec<_>>();
+
+        let mut remaining = (0..rects.len()).collect::<Vec<_>>();
+        remaining.sort_by_key(|&i| -rects[i].area());
+
+        let mut unused = self.stock.iter().map(|s| s.quantity).collect::<Vec<_>>();
+        let mut sheets = Vec::<(usize, Bin)>::new();
+
+        while !remaining.is_empty() {
+            let (k, bin) = (0..self.stock.len())
+                .filter(|&k| unused[k] > 0)
+                .filter_map(|k| {
+                    let bin = self.fill(&packers[k], k, rects, &remaining, rng);
+                    (!bin.items.is_empty()).then_some((k, bin))
+                })
+                .min_by(|(a, a_bin), (b, b_bin)| {
+                    let a_cost = self.stock[*a].cost / used_area(a_bin) as f64;
+                    let b_cost = self.stock[*b].cost / used_area(b_bin) as f64;
+                    a_cost.total_cmp(&b_cost)
+                })
+                .ok_or(StockError::Unplaceable { item: remaining[0] })?;
+
+            remaining.retain(|i| !bin.items.contains(i));
+            unused[k] -= 1;
+            sheets.push((k, bin));
+        }
+
+        for (k, bin) in &mut sheets {
+            let cheaper = (0..self.stock.len())
+                .filter(|&c| unused[c] > 0 && self.stock[c].cost < self.stock[*k].cost)
+                .filter_map(|c| Some((c, self.repack(&packers[c], c, rects, &bin.items, rng)?)))
+                .min_by(|(a, _), (b, _)| self.stock[*a].cost.total_cmp(&self.stock[*b].cost));
+
+            if let Some((c, cheaper_bin)) = cheaper {
+                unused[*k] += 1;
+                unused[c] -= 1;
+                *k = c;
+                *bin = cheaper_bin;
+            }
+        }
+
+        let sheets = sheets
+            .into_iter()
+            .map(|(stock, bin)| Sheet {
+                stock,
+                utilization: used_area(&bin) as f64 / self.stock[stock].bounds.area() as f64,
+                bin,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(StockPacking {
+            cost: sheets.iter().map(|s| self.stock[s.stock].cost).sum(),
+            sheets,
+            unused,
+        })
+    }
+
+    /// Packs as many of the `remaining` rectangles as fit into a sheet of stock `k`, in order.
+    fn fill(
+        &self,
+        packer: &P,
+        k: usize,
+        rects: &[Rect],
+        remaining: &[usize],
+        rng: &mut dyn RngCore,
+    ) -> Bin {
+        let mut bin = Bin::default();
+
+        for &i in remaining {
+            if let Some(added) = add_to_bin(
+                packer,
+                &self.stock[k].bounds,
+                &self.spacing,
+                rects,
+                &bin,
+                i,
+                rng,
+            ) {
+                bin = added;
+            }
+        }
+
+        bin
+    }
+
+    /// Packs all of the `items` into a sheet of stock `k`, returning `None` if they don't fit.
+    fn repack(
+        &self,
+        packer: &P,
+        k: usize,
+        rects: &[Rect],
+        items: &[usize],
+        rng: &mut dyn RngCore,
+    ) -> Option<Bin> {
+        items.iter().try_fold(Bin::default(), |bin, &i| {
+            add_to_bin(
+                packer,
+                &self.stock[k].bounds,
+                &self.spacing,
+                rects,
+                &bin,
+                i,
+                rng,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::algorithms::packing::skyline::{SkylinePackingAlgorithm, SkylineRule};
+
+    use super::*;
+
+    fn square(side: i32) -> Rect {
+        Rect {
+            x1: 0,
+            y1: 0,
+            x2: side,
+            y2: side,
+        }
+    }
+
+    fn stock(side: i32, cost: f64) -> Stock {
+        Stock {
+            bounds: square(side),
+            cost,
+            quantity: 3,
+        }
+    }
+
+    fn algorithm(
+        stock: Vec<Stock>,
+    ) -> VariableBinPackingAlgorithm<
+        SkylinePackingAlgorithm,
+        impl Fn(&Rect) -> SkylinePackingAlgorithm,
+    > {
+        VariableBinPackingAlgorithm {
+            stock,
+            packer: |bounds: &Rect| SkylinePackingAlgorithm {
+                width: Some(bounds.width()),
+                rule: SkylineRule::BottomLeft,
+                allow_rotation: false,
+            },
+            spacing: Spacing::default(),
+        }
+    }
+
+    #[test]
+    fn mixes_stock_sizes_to_save_cost() {
+        // the large sheet can't fit a small square beside the 3 by 3 one, so it takes two of them
+        let rects = [square(3), square(2), square(2)];
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let largest = algorithm(vec![stock(4, 10.0)])
+            .pack_stock(&rects, &mut rng)
+            .unwrap();
+        let mixed = algorithm(vec![stock(4, 10.0), stock(2, 1.0), stock(3, 3.0)])
+            .pack_stock(&rects, &mut rng)
+            .unwrap();
+
+        assert_eq!(largest.cost, 20.0);
+        assert_eq!(mixed.cost, 5.0);
+        assert_eq!(mixed.unused, vec![3, 1, 2]);
+        for sheet in &mixed.sheets {
+            assert_eq!(sheet.utilization, 1.0);
+        }
+    }
+
+    #[test]
+    fn reports_rectangles_larger_than_all_stock() {
+        let rects = [square(2), square(5)];
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let result = algorithm(vec![stock(4, 10.0), stock(2, 1.0)]).pack_stock(&rects, &mut rng);
+        assert_eq!(result.unwrap_err(), StockError::Unplaceable { item: 1 });
+
+        let result = algorithm(vec![stock(4, 10.0), stock(0, 1.0)]).pack_stock(&rects, &mut rng);
+        assert_eq!(result.unwrap_err(), StockError::EmptyStock { stock: 1 });
+    }
+}
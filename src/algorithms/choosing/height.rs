@@ -0,0 +1,32 @@
+use crate::RectChoiceAlgorithm;
+
+pub type MinHeightRectChoiceAlgorithm = MinMaxHeightRectChoiceAlgorithm<true>;
+pub type MaxHeightRectChoiceAlgorithm = MinMaxHeightRectChoiceAlgorithm<false>;
+
+#[doc(hidden)]
+pub struct MinMaxHeightRectChoiceAlgorithm<const CHOOSE_MIN: bool>;
+
+impl<const CHOOSE_MIN: bool> RectChoiceAlgorithm for MinMaxHeightRectChoiceAlgorithm<CHOOSE_MIN> {
+    fn choose(
+        &self,
+        _packing: &[crate::geometry::Rect],
+        choices: &[crate::geometry::Rect],
+        _rng: &mut dyn rand::RngCore,
+    ) -> usize {
+        if CHOOSE_MIN {
+            choices
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.height().cmp(&b.height()))
+                .map(|(i, _)| i)
+                .unwrap_or(0)
+        } else {
+            choices
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.height().cmp(&b.height()))
+                .map(|(i, _)| i)
+                .unwrap_or(0)
+        }
+    }
+}
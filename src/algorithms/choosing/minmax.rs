@@ -11,6 +11,7 @@ impl<const CHOOSE_MIN: bool> RectChoiceAlgorithm for MinMaxRectChoiceAlgorithm<C
         &self,
         _packing: &[crate::geometry::Rect],
         choices: &[crate::geometry::Rect],
+        _rng: &mut dyn rand::RngCore,
     ) -> usize {
         if CHOOSE_MIN {
             choices
@@ -9,8 +9,9 @@ impl RectChoiceAlgorithm for RandomRectChoiceAlgorithm {
         &self,
         _packing: &[crate::geometry::Rect],
         choices: &[crate::geometry::Rect],
+        rng: &mut dyn RngCore,
     ) -> usize {
-        rand::thread_rng().gen_range(0..choices.len())
+        rng.gen_range(0..choices.len())
     }
 
     fn nondeterministic_hint(&self) -> bool {
@@ -0,0 +1,156 @@
+use rand::RngCore;
+
+use crate::geometry::Rect;
+use crate::heuristics::score::score;
+
+use crate::{PackingAlgorithm, PackingError};
+
+/// Packs rectangles with `packer`, then slides them towards the top left corner of the packing
+/// with [compact] to close up the gaps the packer left.
+///
+/// Compaction closes up the gaps between rectangles too, so spacing between them should be added
+/// around this rather than inside it.
+pub struct CompactedPackingAlgorithm<P: PackingAlgorithm> {
+    pub packer: P,
+    /// Regions that are already used, which rectangles stop at like they stop at each other.
+    pub obstacles: Vec<Rect>,
+}
+
+/// How much compaction improved a packing.
+#[derive(Clone, Debug)]
+pub struct Compaction {
+    /// The [score] of the packing as the packer left it.
+    pub before: i32,
+    /// The [score] of the packing after compaction.
+    pub after: i32,
+    /// The number of attempts the packer made.
+    pub attempts: u64,
+}
+
+impl<P: PackingAlgorithm> PackingAlgorithm for CompactedPackingAlgorithm<P> {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        Ok(self.pack_compacted(rects, rng)?.attempts)
+    }
+}
+
+impl<P: PackingAlgorithm> CompactedPackingAlgorithm<P> {
+    /// Packs the `rects` like [PackingAlgorithm::pack], returning the score of the packing before
+    /// and after compaction if a packing was found.
+    pub fn pack_compacted(
+        &self,
+        rects: &mut [Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<Compaction, PackingError> {
+        let result = self.packer.pack(rects, rng)?;
+
+        compact(rects, &self.obstacles);
+        let after = score(rects);
+
+        Ok(Compaction {
+            before: result.score,
+            after,
+            attempts: result.attempts,
+        })
+    }
+}
+
+/// Repeatedly slides each rectangle of `packing` left and then up until it hits another
+/// rectangle, one of the `obstacles` or the left or top edge of the packing's bounding box, until
+/// none of them can move any further.
+///
+/// Rectangles only ever move towards the top left corner of the bounding box, so a valid packing
+/// stays valid, and its bounding box never grows.
+pub fn compact(packing: &mut [Rect], obstacles: &[Rect]) {
+    let bounds = Rect::bbox(packing.iter());
+    let mut order = (0..packing.len()).collect::<Vec<_>>();
+
+    loop {
+        // rectangles nearest the origin settle first, making room for the ones behind them
+        order.sort_by_key(|&i| packing[i].x1 + packing[i].y1);
+
+        let mut moved = false;
+        for &i in &order {
+            let rect = &packing[i];
+            let others = || {
+                packing
+                    .iter()
+                    .enumerate()
+                    .filter(move |&(j, _)| j != i)
+                    .map(|(_, r)| r)
+                    .chain(obstacles)
+            };
+
+            let left = others()
+                .filter(|r| r.y1 < rect.y2 && rect.y1 < r.y2 && r.x2 <= rect.x1)
+                .map(|r| r.x2)
+                .fold(bounds.x1, i32::max)
+                .min(rect.x1);
+            let slid = rect.translated(left - rect.x1, 0);
+
+            let up = others()
+                .filter(|r| r.x1 < slid.x2 && slid.x1 < r.x2 && r.y2 <= slid.y1)
+                .map(|r| r.y2)
+                .fold(bounds.y1, i32::max)
+                .min(slid.y1);
+            let slid = slid.translated(0, up - slid.y1);
+
+            if slid != packing[i] {
+                packing[i] = slid;
+                moved = true;
+            }
+        }
+
+        if !moved {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn closes_gaps_within_the_bounding_box() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..20 {
+            // a grid of cells with a rectangle somewhere inside each, away from the axes
+            let packing = (0..4)
+                .flat_map(|row| (0..4).map(move |col| (row, col)))
+                .map(|(row, col)| {
+                    let (w, h) = (rng.gen_range(1..=6), rng.gen_range(1..=6));
+                    let x = 5 + col * 8 + rng.gen_range(0..=8 - w);
+                    let y = 5 + row * 8 + rng.gen_range(0..=8 - h);
+                    Rect {
+                        x1: x,
+                        y1: y,
+                        x2: x + w,
+                        y2: y + h,
+                    }
+                })
+                .collect::<Vec<_>>();
+            let before = Rect::bbox(packing.iter());
+
+            let mut compacted = packing.clone();
+            compact(&mut compacted, &[]);
+            let after = Rect::bbox(compacted.iter());
+
+            assert!(before.contains(&after), "{before:?} {after:?}");
+            assert_eq!((after.x1, after.y1), (before.x1, before.y1));
+            assert!(score(&compacted) <= score(&packing));
+            for (old, new) in packing.iter().zip(&compacted) {
+                assert_eq!((new.width(), new.height()), (old.width(), old.height()));
+            }
+            for (i, r) in compacted.iter().enumerate() {
+                assert!(
+                    compacted[..i].iter().all(|q| !q.overlaps(r)),
+                    "{compacted:?}"
+                );
+            }
+        }
+    }
+}
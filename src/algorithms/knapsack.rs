@@ -0,0 +1,191 @@
+use rand::RngCore;
+
+use crate::geometry::{Rect, Spacing};
+
+use crate::PackingAlgorithm;
+
+use super::bins::identical::{add_to_bin, Bin};
+
+/// Packs the most valuable subset of rectangles it can find into `bounds`, leaving out whichever
+/// rectangles don't fit.
+///
+/// Rectangles are added one at a time, each kept only if `packer` can still fit everything added
+/// so far within `bounds`. Rectangles that can't fit by area or size alone are left out without
+/// repacking. This is tried both in order of value per unit of area and in order of value, keeping
+/// whichever packs more value.
+pub struct KnapsackPackingAlgorithm<P: PackingAlgorithm> {
+    /// The container to pack into.
+    pub bounds: Rect,
+    /// The packer used to pack the chosen rectangles, which should be configured to pack within
+    /// `bounds` (e.g. a strip as wide as it).
+    pub packer: P,
+    /// Space to keep around the chosen rectangles, checked like
+    /// [BinPackingAlgorithm::spacing](super::bins::identical::BinPackingAlgorithm::spacing).
+    pub spacing: Spacing,
+}
+
+/// The result of a [KnapsackPackingAlgorithm].
+#[derive(Debug, Clone)]
+pub struct KnapsackPacking {
+    /// The indices of the rectangles that were placed, in the same order as `placements`.
+    pub placed: Vec<usize>,
+    /// Where each placed rectangle was placed.
+    pub placements: Vec<Rect>,
+    /// The indices of the rectangles that were left out.
+    pub rejected: Vec<usize>,
+    /// The total value of the placed rectangles.
+    pub value: f64,
+}
+
+impl<P: PackingAlgorithm> KnapsackPackingAlgorithm<P> {
+    /// Chooses which of the `rects` to place and where. Each rectangle is worth the corresponding
+    /// entry of `values`, or its area if `values` is `None`.
+    pub fn pack_knapsack(
+        &self,
+        rects: &[Rect],
+        values: Option<&[f64]>,
+        rng: &mut dyn RngCore,
+    ) -> KnapsackPacking {
+        let values = match values {
+            Some(values) => values.to_vec(),
+            None => rects.iter().map(|r| r.area() as f64).collect(),
+        };
+
+        let mut by_density = (0..rects.len()).collect::<Vec<_>>();
+        by_density.sort_by(|&a, &b| {
+            let a_density = values[a] / rects[a].area() as f64;
+            let b_density = values[b] / rects[b].area() as f64;
+            b_density.total_cmp(&a_density)
+        });
+
+        let mut by_value = (0..rects.len()).collect::<Vec<_>>();
+        by_value.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+
+        let (bin, value) = [by_density, by_value]
+            .iter()
+            .map(|order| self.fill(rects, &values, order, rng))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+
+        KnapsackPacking {
+            rejected: (0..rects.len())
+                .filter(|i| !bin.items.contains(i))
+                .collect(),
+            placed: bin.items,
+            placements: bin.placements,
+            value,
+        }
+    }
+
+    /// Adds each rectangle in `order` to the container if it still fits, returning the contents
+    /// and their total value.
+    fn fill(
+        &self,
+        rects: &[Rect],
+        values: &[f64],
+        order: &[usize],
+        rng: &mut dyn RngCore,
+    ) -> (Bin, f64) {
+        let mut bin = Bin::default();
+        let mut value = 0.0;
+
+        let free_area = self.bounds.width() as i64 * self.bounds.height() as i64;
+        let mut used_area = 0;
+
+        for &i in order {
+            // items worth nothing would only take up space
+            if values[i] <= 0.0 {
+                continue;
+            }
+
+            let rect = &rects[i];
+            let area = rect.width() as i64 * rect.height() as i64;
+            let fits_bounds = rect
+                .orientations(true)
+                .any(|s| s.width <= self.bounds.width() && s.height <= self.bounds.height());
+            if used_area + area > free_area || !fits_bounds {
+                continue;
+            }
+
+            if let Some(added) = add_to_bin(
+                &self.packer,
+                &self.bounds,
+                &self.spacing,
+                rects,
+                &bin,
+                i,
+                rng,
+            ) {
+                bin = added;
+                value += values[i];
+                used_area += area;
+            }
+        }
+
+        (bin, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::algorithms::packing::skyline::{SkylinePackingAlgorithm, SkylineRule};
+
+    use super::*;
+
+    fn algorithm() -> KnapsackPackingAlgorithm<SkylinePackingAlgorithm> {
+        KnapsackPackingAlgorithm {
+            bounds: Rect {
+                x1: 0,
+                y1: 0,
+                x2: 4,
+                y2: 4,
+            },
+            packer: SkylinePackingAlgorithm {
+                width: Some(4),
+                rule: SkylineRule::BottomLeft,
+                allow_rotation: false,
+            },
+            spacing: Spacing::default(),
+        }
+    }
+
+    fn square(side: i32) -> Rect {
+        Rect {
+            x1: 0,
+            y1: 0,
+            x2: side,
+            y2: side,
+        }
+    }
+
+    #[test]
+    fn chooses_the_most_valuable_subset() {
+        // the big square is worth the most on its own, but the small ones are worth more together
+        let rects = [square(4), square(2), square(2), square(2), square(2)];
+        let values = [10.0, 3.0, 3.0, 3.0, 3.0];
+
+        let packing =
+            algorithm().pack_knapsack(&rects, Some(&values), &mut StdRng::seed_from_u64(0));
+
+        assert_eq!(packing.value, 12.0);
+        assert_eq!(packing.rejected, vec![0]);
+        let mut placed = packing.placed.clone();
+        placed.sort_unstable();
+        assert_eq!(placed, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn reports_rectangles_that_dont_fit() {
+        let rects = [square(5), square(4), square(1)];
+
+        let packing = algorithm().pack_knapsack(&rects, None, &mut StdRng::seed_from_u64(0));
+
+        assert_eq!(packing.placed, vec![1]);
+        assert_eq!(packing.rejected, vec![0, 2]);
+        assert_eq!(packing.value, 16.0);
+        assert_eq!(packing.placements, vec![square(4)]);
+    }
+}
@@ -1,9 +1,47 @@
+pub mod alignment;
+
+pub mod anytime;
+
+pub mod bins {
+    pub mod identical;
+    pub mod variable;
+}
+
+pub mod compaction;
+
+pub mod knapsack;
+
+pub mod obstacles;
+
+pub mod portfolio;
+
+pub mod repack;
+
+pub mod spacing;
+
+pub mod online {
+    pub mod network;
+    pub mod shelf;
+    pub mod skyline;
+}
+
 pub mod packing {
+    pub mod annealing;
+    pub mod beam;
+    pub mod exact;
+    pub mod genetic;
+    pub mod guillotine;
     pub mod iterative_random;
+    pub mod maxrects;
+    pub mod mcts;
+    pub mod network;
     pub mod random;
+    pub mod shelf;
+    pub mod skyline;
 }
 
 pub mod choosing {
+    pub mod height;
     pub mod minmax;
     pub mod random;
 }
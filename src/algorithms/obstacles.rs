@@ -0,0 +1,268 @@
+use rand::RngCore;
+
+use crate::geometry::Rect;
+
+use crate::{ObstaclePackingAlgorithm, PackingAlgorithm, PackingError};
+
+/// Packs rectangles with `packer` around the fixed `obstacles`, which `packer` treats as already
+/// placed from the start.
+///
+/// To keep rectangles spaced from the obstacles too, give a
+/// [SpacedPackingAlgorithm](super::spacing::SpacedPackingAlgorithm) as the `packer`.
+pub struct ObstructedPackingAlgorithm<P: ObstaclePackingAlgorithm> {
+    pub packer: P,
+    /// Regions that are already used, which rectangles must not overlap.
+    pub obstacles: Vec<Rect>,
+}
+
+impl<P: ObstaclePackingAlgorithm> PackingAlgorithm for ObstructedPackingAlgorithm<P> {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.packer.try_pack_around(rects, &self.obstacles, rng)
+    }
+}
+
+/// Moves rectangles of a packing up until none of them overlaps the `obstacles` or another
+/// rectangle, for packers that can't place rectangles around obstacles directly.
+///
+/// Rectangles are lifted from the lowest up, each just past whatever it overlaps, so their
+/// horizontal positions and the packing's width are kept.
+pub(crate) fn lift_clear(rects: &mut [Rect], obstacles: &[Rect]) {
+    if obstacles.is_empty() {
+        return;
+    }
+
+    let mut order = (0..rects.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&i| rects[i].y1);
+
+    for (n, &i) in order.iter().enumerate() {
+        let mut rect = rects[i].clone();
+        while let Some(y2) = order[..n]
+            .iter()
+            .map(|&j| &rects[j])
+            .chain(obstacles)
+            .filter(|r| r.overlaps(&rect))
+            .map(|r| r.y2)
+            .max()
+        {
+            rect.y2 += y2 - rect.y1;
+            rect.y1 = y2;
+        }
+        rects[i] = rect;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::algorithms::alignment::AlignedPackingAlgorithm;
+    use crate::algorithms::choosing::height::MaxHeightRectChoiceAlgorithm;
+    use crate::algorithms::choosing::minmax::{MaxRectChoiceAlgorithm, MinRectChoiceAlgorithm};
+    use crate::algorithms::packing::annealing::{AnnealingPackingAlgorithm, CoolingSchedule};
+    use crate::algorithms::packing::beam::BeamPackingAlgorithm;
+    use crate::algorithms::packing::exact::ExactPackingAlgorithm;
+    use crate::algorithms::packing::genetic::{Crossover, GeneticPackingAlgorithm};
+    use crate::algorithms::packing::guillotine::{
+        GuillotineFit, GuillotinePackingAlgorithm, GuillotineSplit,
+    };
+    use crate::algorithms::packing::iterative_random::IterativeRandomPackingAlgorithm;
+    use crate::algorithms::packing::maxrects::{MaxRectsPackingAlgorithm, MaxRectsRule};
+    use crate::algorithms::packing::random::RandomPackingAlgorithm;
+    use crate::algorithms::packing::shelf::{ShelfFit, ShelfPackingAlgorithm};
+    use crate::algorithms::packing::skyline::{SkylinePackingAlgorithm, SkylineRule};
+    use crate::algorithms::spacing::SpacedPackingAlgorithm;
+    use crate::geometry::Spacing;
+    use crate::heuristics::score::ScorePackingHeuristic;
+
+    use super::*;
+
+    /// Packs random rectangles around random obstacles in a strip of the given `width`, checking
+    /// that every rectangle is placed within the strip, clear of the obstacles and of each other.
+    fn check_avoids_obstacles(
+        packer: &impl ObstaclePackingAlgorithm,
+        width: i32,
+        spacing: &Spacing,
+    ) {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..20 {
+            let obstacles = (0..rng.gen_range(1..=4))
+                .map(|_| {
+                    let (x1, y1) = (rng.gen_range(0..width - 4), rng.gen_range(0..20));
+                    Rect {
+                        x1,
+                        y1,
+                        x2: x1 + rng.gen_range(1..=4),
+                        y2: y1 + rng.gen_range(1..=8),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let input = (0..rng.gen_range(1..=15))
+                .map(|_| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: rng.gen_range(1..=6),
+                    y2: rng.gen_range(1..=6),
+                })
+                .collect::<Vec<_>>();
+
+            let algorithm = ObstructedPackingAlgorithm {
+                packer,
+                obstacles: obstacles.clone(),
+            };
+            let mut rects = input.clone();
+            algorithm.pack(&mut rects, &mut rng).unwrap();
+
+            assert!(!Rect::match_items(&input, &rects).contains(&usize::MAX));
+            let bounds = Rect {
+                x1: 0,
+                y1: 0,
+                x2: width,
+                y2: i32::MAX / 2,
+            };
+            assert!(spacing.is_valid(&bounds, &rects, &[]), "{rects:?}");
+            for rect in &rects {
+                assert!(
+                    obstacles
+                        .iter()
+                        .all(|o| !o.overlaps_spaced(rect, spacing.gap)),
+                    "{rect:?} overlaps one of {obstacles:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn packings_avoid_obstacles() {
+        let width = 16;
+
+        for allow_rotation in [false, true] {
+            let skyline = SkylinePackingAlgorithm {
+                width: Some(width),
+                rule: SkylineRule::MinWaste,
+                allow_rotation,
+            };
+            let maxrects = MaxRectsPackingAlgorithm {
+                width: Some(width),
+                rule: MaxRectsRule::BestShortSideFit,
+                allow_rotation,
+            };
+            let guillotine = GuillotinePackingAlgorithm {
+                width: Some(width),
+                fit: GuillotineFit::BestArea,
+                split: GuillotineSplit::ShorterLeftoverAxis,
+                merge: true,
+                allow_rotation,
+            };
+
+            check_avoids_obstacles(&skyline, width, &Spacing::default());
+            check_avoids_obstacles(&maxrects, width, &Spacing::default());
+            check_avoids_obstacles(&guillotine, width, &Spacing::default());
+
+            for fit in [ShelfFit::Next, ShelfFit::First, ShelfFit::Best] {
+                let shelf = ShelfPackingAlgorithm {
+                    width: Some(width),
+                    choice: MaxHeightRectChoiceAlgorithm {},
+                    fit,
+                    allow_rotation,
+                };
+                check_avoids_obstacles(&shelf, width, &Spacing::default());
+            }
+
+            let largest_first = ShelfPackingAlgorithm {
+                width: Some(width),
+                choice: MaxRectChoiceAlgorithm {},
+                fit: ShelfFit::Best,
+                allow_rotation,
+            };
+            let smallest_first = ShelfPackingAlgorithm {
+                width: Some(width),
+                choice: MinRectChoiceAlgorithm {},
+                fit: ShelfFit::First,
+                allow_rotation,
+            };
+            check_avoids_obstacles(&largest_first, width, &Spacing::default());
+            check_avoids_obstacles(&smallest_first, width, &Spacing::default());
+
+            let random = RandomPackingAlgorithm {
+                width: Some(width),
+                allow_rotation,
+            };
+            let iterative_random = IterativeRandomPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                trials_per_iteration: 20,
+                width: Some(width),
+                allow_rotation,
+            };
+            let annealing = AnnealingPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                width: Some(width),
+                iterations: 200,
+                schedule: CoolingSchedule::Linear { initial: 20.0 },
+                allow_rotation,
+            };
+            let genetic = GeneticPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                width: Some(width),
+                population_size: 10,
+                generations: 10,
+                crossover: Crossover::Order,
+                mutation_rate: 0.3,
+                elitism: 1,
+                tournament_size: 2,
+                allow_rotation,
+            };
+            let beam = BeamPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                width: Some(width),
+                beam_width: 4,
+                branching: 4,
+                allow_rotation,
+            };
+            let exact = ExactPackingAlgorithm {
+                width: Some(width),
+                node_limit: Some(1000),
+                time_limit: None,
+                allow_rotation,
+            };
+
+            check_avoids_obstacles(&random, width, &Spacing::default());
+            check_avoids_obstacles(&iterative_random, width, &Spacing::default());
+            check_avoids_obstacles(&annealing, width, &Spacing::default());
+            check_avoids_obstacles(&genetic, width, &Spacing::default());
+            check_avoids_obstacles(&beam, width, &Spacing::default());
+            check_avoids_obstacles(&exact, width, &Spacing::default());
+
+            let aligned = AlignedPackingAlgorithm {
+                packer: SkylinePackingAlgorithm {
+                    width: Some(width / 4),
+                    rule: SkylineRule::BottomLeft,
+                    allow_rotation,
+                },
+                alignment: 4,
+                bounds: None,
+            };
+            check_avoids_obstacles(&aligned, width, &Spacing::default());
+
+            let spacing = Spacing { gap: 1, margin: 1 };
+            let spaced = SpacedPackingAlgorithm {
+                packer: MaxRectsPackingAlgorithm {
+                    width: Some(
+                        spacing
+                            .padded_bounds(&Rect {
+                                x1: 0,
+                                y1: 0,
+                                x2: width,
+                                y2: 0,
+                            })
+                            .width(),
+                    ),
+                    ..maxrects
+                },
+                spacing: spacing.clone(),
+            };
+            check_avoids_obstacles(&spaced, width, &spacing);
+        }
+    }
+}
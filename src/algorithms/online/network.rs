@@ -0,0 +1,86 @@
+use neun::{Model, ModelDriver};
+
+use crate::algorithms::packing::mcts::argmax;
+use crate::geometry::{Rect, Size};
+use crate::nn::{devectorize_position, vectorize_input};
+
+use crate::OnlinePacker;
+
+/// Places each rectangle at the network's favorite valid position within `bounds` as it arrives,
+/// the same way the network places rectangles while training.
+pub struct OnlineNetworkPacker<'a> {
+    /// Runs the model, and is kept between placements rather than made for each one.
+    pub driver: ModelDriver<&'a Model>,
+    pub bounds: Rect,
+    /// Whether rectangles may be rotated by 90 degrees. This must match how the model was trained.
+    pub allow_rotation: bool,
+    /// The multiple that the coordinates of each placement must be, with 1 allowing any position.
+    pub alignment: i32,
+    /// The rectangles placed so far, which may start out with any regions of `bounds` that are
+    /// already used.
+    pub packing: Vec<Rect>,
+}
+
+impl OnlinePacker for OnlineNetworkPacker<'_> {
+    fn place(&mut self, size: &Size) -> Option<Rect> {
+        let input = vectorize_input(
+            &self.bounds,
+            &self.packing,
+            size.width,
+            size.height,
+            self.allow_rotation,
+            self.alignment,
+        );
+        let result = self.driver.run(&input);
+
+        let action = argmax(result.output(), |i| input[i] == 1.0)?;
+        let rect = devectorize_position(&self.bounds, action, size.width, size.height);
+
+        self.packing.push(rect.clone());
+
+        Some(rect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_within_bounds_until_full() {
+        let model = Model::new(&[66, 64]);
+        let mut packer = OnlineNetworkPacker {
+            driver: model.driver(),
+            bounds: Rect {
+                x1: 0,
+                y1: 0,
+                x2: 8,
+                y2: 8,
+            },
+            allow_rotation: false,
+            alignment: 1,
+            packing: vec![],
+        };
+
+        // unit squares fill every cell wherever the network puts them
+        let size = Size {
+            width: 1,
+            height: 1,
+        };
+        let mut placed = vec![];
+        for _ in 0..64 {
+            let rect = packer.place(&size).unwrap();
+
+            assert_eq!((rect.width(), rect.height()), (1, 1));
+            assert!(rect.x1 >= 0 && rect.y1 >= 0 && rect.x2 <= 8 && rect.y2 <= 8);
+            assert!(
+                placed.iter().all(|r: &Rect| !r.overlaps(&rect)),
+                "{placed:?}"
+            );
+            placed.push(rect);
+        }
+
+        assert_eq!(packer.place(&size), None);
+        assert_eq!(packer.packing, placed);
+    }
+}
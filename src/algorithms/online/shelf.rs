@@ -0,0 +1,69 @@
+use crate::algorithms::packing::shelf::{ShelfFit, Shelves};
+use crate::geometry::{Rect, Size};
+
+use crate::OnlinePacker;
+
+/// Places each rectangle on [Shelves] as it arrives.
+pub struct OnlineShelfPacker {
+    pub shelves: Shelves,
+    pub fit: ShelfFit,
+    /// Whether rectangles may be rotated by 90 degrees. A rotatable rectangle is placed in the
+    /// tallest orientation that fits on an existing shelf, or the shortest one on a new shelf.
+    pub allow_rotation: bool,
+}
+
+impl OnlinePacker for OnlineShelfPacker {
+    fn place(&mut self, size: &Size) -> Option<Rect> {
+        self.shelves
+            .place(size.width, size.height, self.fit, self.allow_rotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn placements_are_valid() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for fit in [ShelfFit::Next, ShelfFit::First, ShelfFit::Best] {
+            for allow_rotation in [false, true] {
+                let mut packer = OnlineShelfPacker {
+                    shelves: Shelves::new(10),
+                    fit,
+                    allow_rotation,
+                };
+
+                let mut placed = Vec::<Rect>::new();
+                for _ in 0..20 {
+                    let size = Size {
+                        width: rng.gen_range(1..=6),
+                        height: rng.gen_range(1..=6),
+                    };
+                    let rect = packer.place(&size).unwrap();
+
+                    let dims = (rect.width(), rect.height());
+                    assert!(
+                        dims == (size.width, size.height)
+                            || allow_rotation && dims == (size.height, size.width),
+                        "{fit:?} {rect:?}"
+                    );
+                    assert!(rect.x1 >= 0 && rect.y1 >= 0 && rect.x2 <= 10, "{rect:?}");
+                    assert!(placed.iter().all(|r| !r.overlaps(&rect)), "{placed:?}");
+                    placed.push(rect);
+                }
+
+                // the shelves grow without end, so only rectangles wider than them are refused
+                let wide = Size {
+                    width: 11,
+                    height: 11,
+                };
+                assert_eq!(packer.place(&wide), None);
+            }
+        }
+    }
+}
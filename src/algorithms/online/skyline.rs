@@ -0,0 +1,68 @@
+use crate::algorithms::packing::skyline::{Skyline, SkylineRule};
+use crate::geometry::{Rect, Size};
+
+use crate::OnlinePacker;
+
+/// Places each rectangle against a [Skyline] as it arrives.
+pub struct OnlineSkylinePacker {
+    pub skyline: Skyline,
+    pub rule: SkylineRule,
+    /// Whether rectangles may be rotated by 90 degrees to fit better.
+    pub allow_rotation: bool,
+}
+
+impl OnlinePacker for OnlineSkylinePacker {
+    fn place(&mut self, size: &Size) -> Option<Rect> {
+        self.skyline
+            .place(size.width, size.height, self.rule, self.allow_rotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn placements_are_valid() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for rule in [SkylineRule::BottomLeft, SkylineRule::MinWaste] {
+            for allow_rotation in [false, true] {
+                let mut packer = OnlineSkylinePacker {
+                    skyline: Skyline::new(10),
+                    rule,
+                    allow_rotation,
+                };
+
+                let mut placed = Vec::<Rect>::new();
+                for _ in 0..20 {
+                    let size = Size {
+                        width: rng.gen_range(1..=6),
+                        height: rng.gen_range(1..=6),
+                    };
+                    let rect = packer.place(&size).unwrap();
+
+                    let dims = (rect.width(), rect.height());
+                    assert!(
+                        dims == (size.width, size.height)
+                            || allow_rotation && dims == (size.height, size.width),
+                        "{rule:?} {rect:?}"
+                    );
+                    assert!(rect.x1 >= 0 && rect.y1 >= 0 && rect.x2 <= 10, "{rect:?}");
+                    assert!(placed.iter().all(|r| !r.overlaps(&rect)), "{placed:?}");
+                    placed.push(rect);
+                }
+
+                // the skyline grows without end, so only rectangles wider than it are refused
+                let wide = Size {
+                    width: 11,
+                    height: 11,
+                };
+                assert_eq!(packer.place(&wide), None);
+            }
+        }
+    }
+}
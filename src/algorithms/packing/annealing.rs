@@ -0,0 +1,252 @@
+use std::time::Instant;
+
+use rand::{Rng, RngCore};
+
+use crate::algorithms::anytime::{AnytimePacking, Budget};
+use crate::algorithms::obstacles::lift_clear;
+use crate::geometry::Rect;
+use crate::sequence_pair::SequencePair;
+
+use crate::{
+    AnytimePackingAlgorithm, ObstaclePackingAlgorithm, PackingAlgorithm, PackingError,
+    PackingHeuristic, PackingHeuristicScore,
+};
+
+/// Searches for a packing by simulated annealing over [SequencePair]s, minimizing the given
+/// heuristic.
+pub struct AnnealingPackingAlgorithm<H: PackingHeuristic> {
+    pub heuristic: H,
+    /// The width of the strip to pack into. If given, the search starts from a single column of
+    /// rectangles and rejects any move that makes the packing wider than the strip.
+    pub width: Option<i32>,
+    pub iterations: usize,
+    pub schedule: CoolingSchedule,
+    /// Whether rectangles may be rotated by 90 degrees. If so, some moves rotate a single
+    /// rectangle rather than changing the sequence pair.
+    pub allow_rotation: bool,
+}
+
+/// Determines the temperature at each iteration of an [AnnealingPackingAlgorithm].
+///
+/// Temperatures are in the units of the heuristic's score: a move that makes the score worse by
+/// the current temperature is accepted with a probability of `1 / e`.
+#[derive(Debug, Clone, Copy)]
+pub enum CoolingSchedule {
+    /// Multiplies the temperature by `factor` after every iteration.
+    Geometric { initial: f64, factor: f64 },
+    /// Lowers the temperature linearly from `initial` to zero over the run.
+    Linear { initial: f64 },
+    /// Lowers the temperature quickly at first, then ever more slowly, with higher values of
+    /// `beta` cooling faster.
+    LundyMees { initial: f64, beta: f64 },
+}
+
+impl CoolingSchedule {
+    /// Calculates the temperature at `iteration` out of `iterations`.
+    pub fn temperature(&self, iteration: usize, iterations: usize) -> f64 {
+        match *self {
+            CoolingSchedule::Geometric { initial, factor } => {
+                initial * factor.powi(iteration as i32)
+            }
+            CoolingSchedule::Linear { initial } => {
+                initial * (1.0 - iteration as f64 / iterations as f64)
+            }
+            CoolingSchedule::LundyMees { initial, beta } => {
+                initial / (1.0 + iteration as f64 * beta * initial)
+            }
+        }
+    }
+}
+
+impl<H: PackingHeuristic> PackingAlgorithm for AnnealingPackingAlgorithm<H> {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        let packing = self.pack_anytime(rects, &Budget::default(), rng)?;
+        Ok(packing.iterations as u64)
+    }
+}
+
+/// A sequence pair can't express obstacles, so each decoded packing has its rectangles lifted
+/// clear of them before it is scored.
+impl<H: PackingHeuristic> ObstaclePackingAlgorithm for AnnealingPackingAlgorithm<H> {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let packing = self.search(rects, obstacles, &Budget::default(), rng)?;
+        Ok(packing.iterations as u64)
+    }
+}
+
+/// Each move counts as an iteration. The temperature still follows the schedule over all of
+/// `iterations`, so a search cut short by the budget stops while still warm.
+impl<H: PackingHeuristic> AnytimePackingAlgorithm for AnnealingPackingAlgorithm<H> {
+    type Score = H::Score;
+
+    fn pack_anytime(
+        &self,
+        rects: &mut [Rect],
+        budget: &Budget,
+        rng: &mut dyn RngCore,
+    ) -> Result<AnytimePacking<H::Score>, PackingError> {
+        self.search(rects, &[], budget, rng)
+    }
+}
+
+impl<H: PackingHeuristic> AnnealingPackingAlgorithm<H> {
+    /// Runs the search described on [AnytimePackingAlgorithm::pack_anytime], placing the
+    /// rectangles around the `obstacles`.
+    fn search(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        budget: &Budget,
+        rng: &mut dyn RngCore,
+    ) -> Result<AnytimePacking<H::Score>, PackingError> {
+        let start = Instant::now();
+
+        let mut pair = match self.width {
+            Some(width) => {
+                let fits = |r: &Rect| {
+                    r.orientations(self.allow_rotation)
+                        .any(|s| s.width <= width)
+                };
+                if let Some(item) = rects.iter().position(|r| !fits(r)) {
+                    return Err(PackingError::Unplaceable { item });
+                }
+
+                // stand every rectangle in an orientation that fits the strip
+                for rect in rects.iter_mut() {
+                    if rect.width() > width {
+                        *rect = rect.rotated();
+                    }
+                }
+
+                SequencePair::column(rects.len())
+            }
+            None => SequencePair::random(rects.len(), rng),
+        };
+        pair.decode(rects);
+        lift_clear(rects, obstacles);
+
+        let mut timeline = vec![(start.elapsed(), self.heuristic.score(rects))];
+
+        if rects.len() < 2 {
+            return Ok(AnytimePacking {
+                timeline,
+                iterations: 0,
+            });
+        }
+
+        let mut score = self.heuristic.score(rects);
+        let mut best = (rects.to_vec(), self.heuristic.score(rects));
+        let mut candidate = rects.to_vec();
+
+        let mut iterations = 0;
+        for iteration in 0..self.iterations {
+            if budget.is_exhausted(iteration) {
+                break;
+            }
+            iterations += 1;
+
+            let temperature = self.schedule.temperature(iteration, self.iterations);
+
+            let swap = pair.random_swap(rng);
+            let rotated = (self.allow_rotation && rng.gen_bool(0.25))
+                .then(|| rng.gen_range(0..candidate.len()));
+
+            match rotated {
+                Some(i) => candidate[i] = candidate[i].rotated(),
+                None => pair.apply(swap),
+            }
+            pair.decode(&mut candidate);
+            lift_clear(&mut candidate, obstacles);
+
+            let candidate_score = self.heuristic.score(&candidate);
+            let worse_by = candidate_score.worse_by(&score);
+
+            let fits =
+                !matches!(self.width, Some(width) if Rect::bbox(candidate.iter()).x2 > width);
+
+            let accept = fits
+                && (worse_by <= 0.0
+                    || (temperature > 0.0
+                        && rng.gen_range(0.0..1.0) < (-worse_by / temperature).exp()));
+
+            if accept {
+                if candidate_score.is_better_than(&best.1) {
+                    best = (candidate.clone(), self.heuristic.score(&candidate));
+                    timeline.push((start.elapsed(), self.heuristic.score(&candidate)));
+                }
+                score = candidate_score;
+            } else {
+                match rotated {
+                    Some(i) => candidate[i] = candidate[i].rotated(),
+                    None => pair.apply(swap),
+                }
+            }
+        }
+
+        rects.clone_from_slice(&best.0);
+
+        Ok(AnytimePacking {
+            timeline,
+            iterations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::heuristics::score::{score, ScorePackingHeuristic};
+
+    use super::*;
+
+    #[test]
+    fn improves_on_the_starting_packing() {
+        let algorithm = AnnealingPackingAlgorithm {
+            heuristic: ScorePackingHeuristic,
+            width: None,
+            iterations: 2000,
+            schedule: CoolingSchedule::Geometric {
+                initial: 20.0,
+                factor: 0.998,
+            },
+            allow_rotation: true,
+        };
+
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let rects = (0..12)
+                .map(|_| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: rng.gen_range(1..=10),
+                    y2: rng.gen_range(1..=10),
+                })
+                .collect::<Vec<_>>();
+
+            // without a strip width, the search starts from a random sequence pair
+            let mut start = rects.clone();
+            SequencePair::random(rects.len(), &mut StdRng::seed_from_u64(seed)).decode(&mut start);
+
+            let mut packing = rects.clone();
+            algorithm
+                .pack(&mut packing, &mut StdRng::seed_from_u64(seed))
+                .unwrap();
+
+            assert!(score(&packing) <= score(&start), "seed {seed}");
+            assert!(!Rect::match_items(&rects, &packing).contains(&usize::MAX));
+            for (i, rect) in packing.iter().enumerate() {
+                assert!(
+                    packing[..i].iter().all(|r| !r.overlaps(rect)),
+                    "seed {seed}"
+                );
+            }
+        }
+    }
+}
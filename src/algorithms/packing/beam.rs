@@ -0,0 +1,282 @@
+use rayon::prelude::*;
+
+use rand::RngCore;
+
+use crate::geometry::Rect;
+
+use crate::{
+    ObstaclePackingAlgorithm, PackingAlgorithm, PackingError, PackingHeuristic,
+    PackingHeuristicScore,
+};
+
+/// Places the rectangles from largest to smallest, keeping the best `beam_width` partial packings
+/// at each step as ranked by the given heuristic.
+///
+/// One of the partial packings always places each rectangle where it scores best, so the result is
+/// never worse than that greedy packing, although a wider beam can still end up worse than a
+/// narrower one.
+///
+/// Each partial packing is extended by trying the next rectangle at every corner point of the
+/// rectangles already placed. Partial packings are extended in parallel.
+pub struct BeamPackingAlgorithm<H: PackingHeuristic> {
+    pub heuristic: H,
+    /// The width of the strip to pack into. If `None`, the packing may grow in any direction.
+    pub width: Option<i32>,
+    /// The number of partial packings kept at each step.
+    pub beam_width: usize,
+    /// The number of extensions of each partial packing that are considered for the next step.
+    pub branching: usize,
+    /// Whether rectangles may be rotated by 90 degrees, doubling the extensions considered.
+    pub allow_rotation: bool,
+}
+
+impl<H> PackingAlgorithm for BeamPackingAlgorithm<H>
+where
+    H: PackingHeuristic + Sync,
+    H::Score: Send,
+{
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.try_pack_around(rects, &[], rng)
+    }
+}
+
+/// The corner points of the obstacles are tried too, and positions that overlap an obstacle are
+/// skipped.
+impl<H> ObstaclePackingAlgorithm for BeamPackingAlgorithm<H>
+where
+    H: PackingHeuristic + Sync,
+    H::Score: Send,
+{
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        _rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        rects.sort_by_key(|r| -r.area());
+
+        let mut beam = vec![Vec::with_capacity(rects.len())];
+        let mut attempts = 0;
+
+        for (i, rect) in rects.iter().enumerate() {
+            let mut children = beam
+                .par_iter()
+                .flat_map_iter(|packing| self.expand(packing, obstacles, rect))
+                .collect::<Vec<_>>();
+
+            if children.is_empty() {
+                return Err(PackingError::Unplaceable { item: i });
+            }
+            attempts += children.len() as u64;
+
+            // the best extension of the first packing stays first, so that it follows the greedy
+            // packing and the rest of the beam can only improve on it
+            let lead = children.remove(0);
+            children.sort_by(|(_, a), (_, b)| a.cmp_better(b));
+            children.truncate(self.beam_width.max(1) - 1);
+            children.insert(0, lead);
+
+            beam = children.into_iter().map(|(packing, _)| packing).collect();
+        }
+
+        let best = beam
+            .iter()
+            .min_by(|a, b| self.heuristic.score(a).cmp_better(&self.heuristic.score(b)))
+            .unwrap();
+        rects.clone_from_slice(best);
+
+        Ok(attempts)
+    }
+}
+
+impl<H: PackingHeuristic> BeamPackingAlgorithm<H> {
+    /// Returns the best `branching` ways to add `rect` to `packing` at one of the corner points of
+    /// it and the `obstacles`, along with their scores.
+    fn expand(
+        &self,
+        packing: &[Rect],
+        obstacles: &[Rect],
+        rect: &Rect,
+    ) -> Vec<(Vec<Rect>, H::Score)> {
+        let fixed = packing.iter().chain(obstacles).cloned().collect::<Vec<_>>();
+        let points = corner_points(&fixed, (0, 0));
+
+        let mut placements = rect
+            .orientations(self.allow_rotation)
+            .flat_map(|size| {
+                points.iter().map(move |&(x, y)| Rect {
+                    x1: x,
+                    y1: y,
+                    x2: x + size.width,
+                    y2: y + size.height,
+                })
+            })
+            .filter(|placed| {
+                !matches!(self.width, Some(width) if placed.x2 > width)
+                    && !fixed.iter().any(|p| p.overlaps(placed))
+            })
+            .collect::<Vec<_>>();
+
+        // both orientations of a square land in the same place, which would take up two branches
+        placements.sort_unstable_by_key(|r| (r.x1, r.y1, r.x2, r.y2));
+        placements.dedup();
+
+        let mut children = placements
+            .into_iter()
+            .map(|placed| {
+                let mut child = Vec::with_capacity(packing.len() + 1);
+                child.extend_from_slice(packing);
+                child.push(placed);
+
+                let score = self.heuristic.score(&child);
+                (child, score)
+            })
+            .collect::<Vec<_>>();
+
+        children.sort_by(|(_, a), (_, b)| a.cmp_better(b));
+        children.truncate(self.branching.max(1));
+        children
+    }
+}
+
+/// Returns the candidate positions for a new rectangle: the `origin`, and the corners just past
+/// each rectangle in `packing` along either axis, both as they are and projected onto the axes
+/// through the `origin`.
+pub(crate) fn corner_points(packing: &[Rect], origin: (i32, i32)) -> Vec<(i32, i32)> {
+    let (x0, y0) = origin;
+    let mut points = std::iter::once(origin)
+        .chain(
+            packing
+                .iter()
+                .flat_map(|r| [(r.x2, r.y1), (r.x1, r.y2), (r.x2, y0), (x0, r.y2)]),
+        )
+        .collect::<Vec<_>>();
+
+    points.sort_unstable();
+    points.dedup();
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::heuristics::score::{score, ScorePackingHeuristic};
+
+    use super::*;
+
+    fn random_rects(rng: &mut StdRng) -> Vec<Rect> {
+        (0..10)
+            .map(|_| Rect {
+                x1: 0,
+                y1: 0,
+                x2: rng.gen_range(1..=8),
+                y2: rng.gen_range(1..=8),
+            })
+            .collect()
+    }
+
+    fn beam(
+        width: Option<i32>,
+        beam_width: usize,
+        branching: usize,
+    ) -> BeamPackingAlgorithm<ScorePackingHeuristic> {
+        BeamPackingAlgorithm {
+            heuristic: ScorePackingHeuristic,
+            width,
+            beam_width,
+            branching,
+            allow_rotation: true,
+        }
+    }
+
+    #[test]
+    fn packings_are_valid() {
+        for seed in 0..10 {
+            let rects = random_rects(&mut StdRng::seed_from_u64(seed));
+
+            for width in [None, Some(12)] {
+                let mut packing = rects.clone();
+                beam(width, 4, 3)
+                    .pack(&mut packing, &mut StdRng::seed_from_u64(seed))
+                    .unwrap();
+
+                assert!(!Rect::match_items(&rects, &packing).contains(&usize::MAX));
+                if let Some(width) = width {
+                    assert!(packing.iter().all(|r| r.x2 <= width), "seed {seed}");
+                }
+                for (i, rect) in packing.iter().enumerate() {
+                    assert!(
+                        packing[..i].iter().all(|r| !r.overlaps(rect)),
+                        "seed {seed}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn narrowest_beam_is_greedy() {
+        for seed in 0..10 {
+            let mut rects = random_rects(&mut StdRng::seed_from_u64(seed));
+
+            let mut packing = rects.clone();
+            beam(None, 1, 1)
+                .pack(&mut packing, &mut StdRng::seed_from_u64(seed))
+                .unwrap();
+
+            // place each rectangle, largest first, wherever it scores best
+            rects.sort_by_key(|r| -r.area());
+            let mut greedy = Vec::<Rect>::new();
+            for rect in &rects {
+                let mut placements = corner_points(&greedy, (0, 0))
+                    .into_iter()
+                    .flat_map(|(x, y)| {
+                        rect.orientations(true).map(move |size| Rect {
+                            x1: x,
+                            y1: y,
+                            x2: x + size.width,
+                            y2: y + size.height,
+                        })
+                    })
+                    .filter(|placed| !greedy.iter().any(|r| r.overlaps(placed)))
+                    .collect::<Vec<_>>();
+                placements.sort_unstable_by_key(|r| (r.x1, r.y1, r.x2, r.y2));
+                placements.dedup();
+
+                greedy = placements
+                    .into_iter()
+                    .map(|placed| {
+                        let mut child = greedy.clone();
+                        child.push(placed);
+                        child
+                    })
+                    .min_by_key(|child| score(child))
+                    .unwrap();
+            }
+
+            assert_eq!(packing, greedy, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn wider_beams_score_no_worse_than_greedy() {
+        for seed in 0..10 {
+            let rects = random_rects(&mut StdRng::seed_from_u64(seed));
+
+            let scores = [1, 4, 16].map(|beam_width| {
+                let mut packing = rects.clone();
+                beam(None, beam_width, 4)
+                    .pack(&mut packing, &mut StdRng::seed_from_u64(seed))
+                    .unwrap();
+                score(&packing)
+            });
+
+            assert!(
+                scores.iter().all(|&s| s <= scores[0]),
+                "seed {seed} {scores:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,874 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use rand::RngCore;
+
+use crate::geometry::Rect;
+
+use crate::algorithms::obstacles::lift_clear;
+use crate::heuristics::height::{height, height_lower_bound};
+use crate::{ObstaclePackingAlgorithm, PackingAlgorithm, PackingError};
+
+use super::skyline::{SkylinePackingAlgorithm, SkylineRule};
+
+/// Finds a packing with the minimal possible [score](crate::heuristics::score::score) using branch
+/// and bound, or with the minimal possible [height] if a strip `width` is given.
+///
+/// Candidate bounding boxes are tried in order of increasing area, or increasing height in a strip,
+/// and the first one that the rectangles can be packed into gives an optimal packing. This is only
+/// practical for small instances of around ten rectangles or fewer.
+pub struct ExactPackingAlgorithm {
+    /// The width of the strip to pack into. If `None`, any bounding box may be used.
+    pub width: Option<i32>,
+    /// The maximum number of search nodes to visit before giving up.
+    pub node_limit: Option<u64>,
+    /// The maximum amount of time to search for before giving up.
+    pub time_limit: Option<Duration>,
+    /// Whether rectangles may be rotated by 90 degrees. This roughly doubles the branching of the
+    /// search for every rectangle that isn't square.
+    pub allow_rotation: bool,
+}
+
+/// Describes how an [ExactPackingAlgorithm] search ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExactStatus {
+    /// The search finished, so the packing is optimal.
+    Optimal,
+    /// The search stopped after visiting the maximum number of nodes.
+    NodeLimit,
+    /// The search stopped after running for the maximum amount of time.
+    TimeLimit,
+    /// Some rectangle is wider than the strip, so there is no packing.
+    Infeasible,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExactOutcome {
+    pub status: ExactStatus,
+    /// The number of search nodes visited.
+    pub nodes: u64,
+    /// A lower bound on the [score](crate::heuristics::score::score) of any packing of the
+    /// rectangles, or on its [height] in a strip. If the search finished, this is the score or
+    /// height of the packing that was found.
+    pub lower_bound: i64,
+}
+
+impl PackingAlgorithm for ExactPackingAlgorithm {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        let outcome = self.solve(rects, rng);
+
+        if outcome.status == ExactStatus::Infeasible {
+            // only a rectangle that's too wide for the strip makes the search infeasible
+            let width = self.width.unwrap_or(i32::MAX);
+            let item = rects
+                .iter()
+                .position(|r| r.orientations(self.allow_rotation).all(|s| s.width > width))
+                .unwrap_or(0);
+            return Err(PackingError::Unplaceable { item });
+        }
+
+        Ok(outcome.nodes)
+    }
+}
+
+/// The search doesn't know about obstacles, so the packing it finds has its rectangles lifted
+/// clear of them afterwards, and is then no longer optimal.
+impl ObstaclePackingAlgorithm for ExactPackingAlgorithm {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let nodes = self.try_pack(rects, rng)?;
+        lift_clear(rects, obstacles);
+        Ok(nodes)
+    }
+}
+
+impl ExactPackingAlgorithm {
+    /// Packs `rects` as tightly as possible, leaving the best packing found in `rects` even if the
+    /// search stops early. The search itself is deterministic, and only the initial packing found
+    /// with a skyline is given `rng`.
+    pub fn solve(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> ExactOutcome {
+        if rects.is_empty() {
+            return ExactOutcome {
+                status: ExactStatus::Optimal,
+                nodes: 0,
+                lower_bound: 0,
+            };
+        }
+
+        let total_area = rects
+            .iter()
+            .map(|r| r.width() as i64 * r.height() as i64)
+            .sum::<i64>();
+
+        // in a packing pushed as far towards the origin as it goes, every coordinate is a sum of
+        // some of the rectangles' sizes, so only those need to be considered as bounding boxes
+        let widths = match self.width {
+            Some(width) => {
+                if height_lower_bound(rects, width, self.allow_rotation).is_none() {
+                    return ExactOutcome {
+                        status: ExactStatus::Infeasible,
+                        nodes: 0,
+                        lower_bound: 0,
+                    };
+                }
+                vec![width]
+            }
+            None => self.subset_sums(rects, Rect::width),
+        };
+        let heights = self.subset_sums(rects, Rect::height);
+
+        let incumbent = self.find_incumbent(rects, &incumbent_widths(&widths, total_area), rng);
+
+        let mut candidates = widths
+            .iter()
+            .flat_map(|&w| heights.iter().map(move |&h| (w, h)))
+            .filter(|&(w, h)| w as i64 * h as i64 >= total_area)
+            // a packing turned on its side fits the container turned on its side, so when every
+            // rectangle may be turned only one of the two needs to be searched
+            .filter(|&(w, h)| self.width.is_some() || !self.allow_rotation || w <= h)
+            .map(|(w, h)| (self.bound(w, h, total_area), w, h))
+            .filter(|&(bound, _, _)| bound < incumbent)
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|&(bound, w, _)| (bound, w));
+
+        let mut search = Search::new(rects, self.allow_rotation, self.node_limit, self.time_limit);
+
+        for (bound, width, height) in candidates {
+            match search.fits(width, height) {
+                Ok(true) => {
+                    search.write_placements(rects);
+                    return ExactOutcome {
+                        status: ExactStatus::Optimal,
+                        nodes: search.nodes,
+                        lower_bound: self.objective(rects),
+                    };
+                }
+                Ok(false) => (),
+                Err(status) => {
+                    return ExactOutcome {
+                        status,
+                        nodes: search.nodes,
+                        // every candidate's bound is below the incumbent's objective
+                        lower_bound: bound,
+                    };
+                }
+            }
+        }
+
+        ExactOutcome {
+            status: ExactStatus::Optimal,
+            nodes: search.nodes,
+            lower_bound: self.objective(rects),
+        }
+    }
+
+    /// Returns the value being minimized for `packing`: its
+    /// [score](crate::heuristics::score::score), or its [height] in a strip.
+    ///
+    /// The score is calculated with 64-bit areas, since the bounding boxes of large rectangles can
+    /// have more area than fits in an `i32`.
+    fn objective(&self, packing: &[Rect]) -> i64 {
+        match self.width {
+            Some(_) => height(packing) as i64,
+            None => {
+                let bbox = Rect::bbox(packing.iter());
+                let area = packing
+                    .iter()
+                    .map(|r| r.width() as i64 * r.height() as i64)
+                    .sum::<i64>();
+                bbox.width() as i64 * bbox.height() as i64 - area
+            }
+        }
+    }
+
+    /// Returns the best objective that a packing into a `width` by `height` container could have.
+    fn bound(&self, width: i32, height: i32, total_area: i64) -> i64 {
+        match self.width {
+            Some(_) => height as i64,
+            None => width as i64 * height as i64 - total_area,
+        }
+    }
+
+    /// Packs `rects` with a skyline at each of the `widths`, leaving the best result in `rects`
+    /// and returning its objective.
+    fn find_incumbent(&self, rects: &mut [Rect], widths: &[i32], rng: &mut dyn RngCore) -> i64 {
+        let mut best = i64::MAX;
+        let mut attempt = rects.to_vec();
+
+        for &width in widths {
+            let skyline = SkylinePackingAlgorithm {
+                width: Some(width),
+                rule: SkylineRule::BottomLeft,
+                allow_rotation: self.allow_rotation,
+            };
+
+            if skyline.try_pack(&mut attempt, rng).is_ok() {
+                let objective = self.objective(&attempt);
+                if objective < best {
+                    best = objective;
+                    rects.clone_from_slice(&attempt);
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Returns every sum of the lengths of a subset of the `rects` along one axis that is at least
+    /// as long as every rectangle, in increasing order. A rotatable rectangle may contribute either
+    /// of its sides.
+    fn subset_sums(&self, rects: &[Rect], length: fn(&Rect) -> i32) -> Vec<i32> {
+        let options = rects
+            .iter()
+            .map(|r| {
+                if self.allow_rotation {
+                    (r.width(), r.height())
+                } else {
+                    (length(r), length(r))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let min = options.iter().map(|&(a, b)| a.min(b)).max().unwrap();
+
+        let mut reachable = vec![true];
+        for &(a, b) in options.iter() {
+            let (short, long) = (a.min(b) as usize, a.max(b) as usize);
+            reachable.resize(reachable.len() + long, false);
+            for sum in (short..reachable.len()).rev() {
+                reachable[sum] |= reachable[sum - short] || (sum >= long && reachable[sum - long]);
+            }
+        }
+
+        reachable
+            .iter()
+            .enumerate()
+            .filter(|&(sum, &r)| r && sum as i32 >= min)
+            .map(|(sum, _)| sum as i32)
+            .collect()
+    }
+}
+
+/// The multiples of the square root of the total area around which widths are tried for the
+/// initial packing.
+const INCUMBENT_WIDTH_FACTORS: [f64; 5] = [0.5, 0.75, 1.0, 1.5, 2.0];
+
+/// Chooses the few of the increasing candidate `widths` that are closest to a multiple of the
+/// square root of `total_area` from [INCUMBENT_WIDTH_FACTORS], since a skyline packs best at
+/// roughly square widths and trying every candidate would take a skyline packing each.
+fn incumbent_widths(widths: &[i32], total_area: i64) -> Vec<i32> {
+    let side = (total_area as f64).sqrt();
+
+    let mut chosen = INCUMBENT_WIDTH_FACTORS
+        .iter()
+        .filter_map(|factor| {
+            let target = factor * side;
+            let i = widths.partition_point(|&w| (w as f64) < target);
+            // the closest width is either the first one past the target or the one before it
+            [i.checked_sub(1), Some(i)]
+                .into_iter()
+                .flatten()
+                .filter_map(|i| widths.get(i).copied())
+                .min_by(|&a, &b| {
+                    (a as f64 - target)
+                        .abs()
+                        .total_cmp(&(b as f64 - target).abs())
+                })
+        })
+        .collect::<Vec<_>>();
+
+    chosen.dedup();
+    chosen
+}
+
+/// A group of identically sized rectangles, which are interchangeable during the search.
+struct Group {
+    width: i32,
+    height: i32,
+    /// Whether the members may also be placed turned by 90 degrees.
+    rotatable: bool,
+    /// The indices of the rectangles in this group.
+    members: Vec<usize>,
+    /// The number of members already placed.
+    placed: usize,
+}
+
+impl Group {
+    /// Returns the sizes that the members may be placed with.
+    fn orientations(&self) -> impl Iterator<Item = (i32, i32)> {
+        let turned = self.rotatable.then_some((self.height, self.width));
+        std::iter::once((self.width, self.height)).chain(turned)
+    }
+}
+
+/// A partial packing, by the number of members of each group placed and the corner points of
+/// the envelope.
+type State = (Vec<usize>, Vec<(i32, i32)>);
+
+/// The state of the search for a packing into a fixed bounding box.
+///
+/// Rectangles are placed one at a time at the corner points of the envelope of those already
+/// placed, which is the region below or to the left of some placed rectangle. Any packing can be
+/// pushed down and left until, in some order, each rectangle lies on a corner point of the ones
+/// before it, so only those positions are tried. The search branches once per rectangle rather
+/// than once per unit of the container, so its cost doesn't grow with the sizes involved.
+struct Search {
+    groups: Vec<Group>,
+    remaining: usize,
+    /// Where each rectangle was placed, indexed like the rectangles given to [Search::new].
+    placements: Vec<Rect>,
+    /// The indices in [Search::placements] of the rectangles placed so far.
+    placed: Vec<usize>,
+    placed_area: i64,
+    width: i32,
+    height: i32,
+    waste_budget: i64,
+    /// The values of `k` to round the rectangles' widths and heights with, as in
+    /// [Search::ruled_out].
+    thresholds: (Vec<i32>, Vec<i32>),
+    /// The states already found to have no packing. Placing the same rectangles in another order
+    /// often leads back to one of these.
+    dead_ends: HashSet<State>,
+    /// The largest group with a single member, which is only placed in the lower left quarter of
+    /// the positions it can take. Any packing can be mirrored to put it there.
+    anchor: Option<usize>,
+    nodes: u64,
+    node_limit: Option<u64>,
+    deadline: Option<Instant>,
+}
+
+impl Search {
+    fn new(
+        rects: &[Rect],
+        allow_rotation: bool,
+        node_limit: Option<u64>,
+        time_limit: Option<Duration>,
+    ) -> Search {
+        let mut groups = Vec::<Group>::new();
+        for (i, rect) in rects.iter().enumerate() {
+            // rotatable rectangles are grouped regardless of their orientation
+            let (width, height) = if allow_rotation {
+                let (w, h) = (rect.width(), rect.height());
+                (w.min(h), w.max(h))
+            } else {
+                (rect.width(), rect.height())
+            };
+
+            match groups
+                .iter_mut()
+                .find(|g| g.width == width && g.height == height)
+            {
+                Some(group) => group.members.push(i),
+                None => groups.push(Group {
+                    width,
+                    height,
+                    rotatable: allow_rotation && width != height,
+                    members: vec![i],
+                    placed: 0,
+                }),
+            }
+        }
+
+        // trying large rectangles first finds dead ends sooner
+        groups.sort_by_key(|g| -(g.width as i64 * g.height as i64));
+        let anchor = groups.iter().position(|g| g.members.len() == 1);
+
+        Search {
+            groups,
+            remaining: rects.len(),
+            placements: rects.to_vec(),
+            placed: Vec::with_capacity(rects.len()),
+            placed_area: 0,
+            width: 0,
+            height: 0,
+            waste_budget: 0,
+            thresholds: (vec![], vec![]),
+            dead_ends: HashSet::new(),
+            anchor,
+            nodes: 0,
+            node_limit,
+            deadline: time_limit.map(|limit| Instant::now() + limit),
+        }
+    }
+
+    /// Returns whether the rectangles can be packed into a `width` by `height` container, leaving
+    /// the packing in [Search::placements] if so.
+    fn fits(&mut self, width: i32, height: i32) -> Result<bool, ExactStatus> {
+        let total_area = self
+            .groups
+            .iter()
+            .map(|g| g.width as i64 * g.height as i64 * g.members.len() as i64)
+            .sum::<i64>();
+
+        self.width = width;
+        self.height = height;
+        self.waste_budget = width as i64 * height as i64 - total_area;
+        self.dead_ends.clear();
+
+        let all_fit = self
+            .groups
+            .iter()
+            .all(|g| g.orientations().any(|(w, h)| w <= width && h <= height));
+        if !all_fit {
+            return Ok(false);
+        }
+
+        self.thresholds = (self.thresholds(width), self.thresholds(height));
+        if self.ruled_out() {
+            return Ok(false);
+        }
+
+        self.step()
+    }
+
+    /// Returns whether the rectangles are shown not to fit into the container by rounding their
+    /// sizes, which keeps the area of any packing within the container's area.
+    ///
+    /// A side within `k` of the container's side is rounded up to the whole side, since nothing
+    /// else fits beside it, and a side shorter than `k` is rounded down to nothing, since at most
+    /// one side of the container's length beside it is longer than its side. Trying `k` at each
+    /// short side rules out many containers that have enough area without searching.
+    fn ruled_out(&self) -> bool {
+        let area = self.width as i64 * self.height as i64;
+
+        for &kw in self.thresholds.0.iter() {
+            for &kh in self.thresholds.1.iter() {
+                let total = self
+                    .groups
+                    .iter()
+                    .map(|g| {
+                        self.rounded(g, |w, h| {
+                            round(w, self.width, kw) * round(h, self.height, kh)
+                        }) * g.members.len() as i64
+                    })
+                    .sum::<i64>();
+
+                if total > area {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Returns the lengths to try as `k` when rounding sides along a `side` of the container: 0,
+    /// which leaves sides unchanged, and each side of a rectangle up to half of `side`.
+    fn thresholds(&self, side: i32) -> Vec<i32> {
+        let mut thresholds = vec![0];
+        for g in self.groups.iter() {
+            thresholds.extend(
+                [g.width, g.height]
+                    .into_iter()
+                    .filter(|&l| 0 < l && 2 * l <= side),
+            );
+        }
+        thresholds.sort_unstable();
+        thresholds.dedup();
+        thresholds
+    }
+
+    /// Returns the smallest `area` of a member of `g` over the orientations that fit in the
+    /// container, of which there must be at least one.
+    fn rounded(&self, g: &Group, area: impl Fn(i32, i32) -> i64) -> i64 {
+        g.orientations()
+            .filter(|&(w, h)| w <= self.width && h <= self.height)
+            .map(|(w, h)| area(w, h))
+            .min()
+            .unwrap()
+    }
+
+    fn step(&mut self) -> Result<bool, ExactStatus> {
+        if self.remaining == 0 {
+            return Ok(true);
+        }
+
+        self.nodes += 1;
+        if matches!(self.node_limit, Some(limit) if self.nodes > limit) {
+            return Err(ExactStatus::NodeLimit);
+        }
+        if self.nodes & 1023 == 0 && matches!(self.deadline, Some(d) if Instant::now() >= d) {
+            return Err(ExactStatus::TimeLimit);
+        }
+
+        let (corners, envelope_area) = self.corners();
+        if self.waste(&corners, envelope_area) > self.waste_budget || self.overfull(&corners) {
+            return Ok(false);
+        }
+
+        let state: State = (
+            self.groups.iter().map(|g| g.placed).collect::<Vec<_>>(),
+            corners,
+        );
+        if self.dead_ends.contains(&state) {
+            return Ok(false);
+        }
+
+        let corners = &state.1;
+        let envelope_waste = envelope_area - self.placed_area;
+
+        for (k, &(x, y)) in corners.iter().enumerate() {
+            for g in 0..self.groups.len() {
+                let group = &self.groups[g];
+                if group.placed == group.members.len() {
+                    continue;
+                }
+
+                let member = group.members[group.placed];
+                let orientations = [(group.width, group.height), (group.height, group.width)];
+                let orientations = &orientations[..if group.rotatable { 2 } else { 1 }];
+
+                for &(width, height) in orientations {
+                    if x + width > self.width || y + height > self.height {
+                        continue;
+                    }
+                    if Some(g) == self.anchor
+                        && (2 * x + width > self.width || 2 * y + height > self.height)
+                    {
+                        continue;
+                    }
+
+                    // checking the envelope here saves setting up most of the nodes that fail
+                    let added = self.added_area(corners, k, width, height);
+                    if envelope_waste + added - width as i64 * height as i64 > self.waste_budget {
+                        continue;
+                    }
+
+                    self.groups[g].placed += 1;
+                    self.remaining -= 1;
+                    self.placements[member] = Rect {
+                        x1: x,
+                        y1: y,
+                        x2: x + width,
+                        y2: y + height,
+                    };
+                    self.placed.push(member);
+                    self.placed_area += width as i64 * height as i64;
+
+                    let result = self.step();
+
+                    self.placed_area -= width as i64 * height as i64;
+                    self.placed.pop();
+                    self.remaining += 1;
+                    self.groups[g].placed -= 1;
+
+                    if result != Ok(false) {
+                        return result;
+                    }
+                }
+            }
+        }
+
+        self.dead_ends.insert(state);
+        Ok(false)
+    }
+
+    /// Returns the corner points of the envelope of the placed rectangles from left to right,
+    /// which is from top to bottom, along with the area of the envelope.
+    fn corners(&self) -> (Vec<(i32, i32)>, i64) {
+        let mut ends = self
+            .placed
+            .iter()
+            .map(|&i| (self.placements[i].x2, self.placements[i].y2))
+            .collect::<Vec<_>>();
+        ends.sort_unstable_by(|a, b| b.cmp(a));
+
+        // walking from right to left, the envelope steps up at the end of each rectangle that's
+        // taller than everything to its right
+        let mut corners = vec![];
+        let mut area = 0;
+        let (mut top, mut right) = (0, self.width);
+        for (x, y) in ends {
+            if y > top {
+                corners.push((x, top));
+                area += top as i64 * (right - x) as i64;
+                (top, right) = (y, x);
+            }
+        }
+        corners.push((0, top));
+        area += top as i64 * right as i64;
+
+        corners.reverse();
+        (corners, area)
+    }
+
+    /// Returns the area that placing a `width` by `height` rectangle at corner point `k` adds to
+    /// the envelope.
+    fn added_area(&self, corners: &[(i32, i32)], k: usize, width: i32, height: i32) -> i64 {
+        let (right, top) = (corners[k].0 + width, corners[k].1 + height);
+
+        // the envelope is at the height of each corner point up to the next one
+        let mut added = 0;
+        for (c, &(x, y)) in corners.iter().enumerate() {
+            if x >= right {
+                break;
+            }
+            let next = corners.get(c + 1).map_or(self.width, |c| c.0);
+            added += (next.min(right) - x) as i64 * (top - y).max(0) as i64;
+        }
+
+        added
+    }
+
+    /// Returns the area that no remaining rectangle can use: the area of the envelope not covered
+    /// by placed rectangles, and the space above each corner point that nothing fits at.
+    fn waste(&self, corners: &[(i32, i32)], envelope_area: i64) -> i64 {
+        let mut waste = envelope_area - self.placed_area;
+
+        for (k, &(x, y)) in corners.iter().enumerate() {
+            let fits = self
+                .groups
+                .iter()
+                .filter(|g| g.placed < g.members.len())
+                .any(|g| {
+                    g.orientations()
+                        .any(|(w, h)| x + w <= self.width && y + h <= self.height)
+                });
+
+            if !fits {
+                // only rectangles placed at this corner could fill the space above it up to the
+                // previous step, and later corners there have even less room
+                let right = corners.get(k + 1).map_or(self.width, |c| c.0);
+                let top = k.checked_sub(1).map_or(self.height, |k| corners[k].1);
+                waste += (right - x) as i64 * (top - y) as i64;
+            }
+        }
+
+        waste
+    }
+
+    /// Returns whether the remaining rectangles can't fit beside the envelope. Each row of the
+    /// container holds the envelope and the rectangles crossing it, so with their widths rounded
+    /// as in [Search::ruled_out], the rectangles' total over all rows can't exceed the rows' free
+    /// widths. The same goes for the columns.
+    fn overfull(&self, corners: &[(i32, i32)]) -> bool {
+        let remaining = || self.groups.iter().filter(|g| g.placed < g.members.len());
+
+        for &k in self.thresholds.0.iter() {
+            let round = |length| round(length, self.width, k);
+            let needed = remaining()
+                .map(|g| self.rounded(g, |w, h| round(w) * h as i64))
+                .sum::<i64>();
+            let free = (0..corners.len())
+                .map(|c| {
+                    let top = c.checked_sub(1).map_or(self.height, |c| corners[c].1);
+                    (self.width as i64 - round(corners[c].0)) * (top - corners[c].1) as i64
+                })
+                .sum::<i64>();
+
+            if needed > free {
+                return true;
+            }
+        }
+
+        for &k in self.thresholds.1.iter() {
+            let round = |length| round(length, self.height, k);
+            let needed = remaining()
+                .map(|g| self.rounded(g, |w, h| w as i64 * round(h)))
+                .sum::<i64>();
+            let free = (0..corners.len())
+                .map(|c| {
+                    let right = corners.get(c + 1).map_or(self.width, |c| c.0);
+                    (self.height as i64 - round(corners[c].1)) * (right - corners[c].0) as i64
+                })
+                .sum::<i64>();
+
+            if needed > free {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn write_placements(&self, rects: &mut [Rect]) {
+        rects.clone_from_slice(&self.placements);
+    }
+}
+
+/// Rounds a `length` along a `side` of the container as described in [Search::ruled_out].
+fn round(length: i32, side: i32, k: i32) -> i64 {
+    if length > side - k {
+        side as i64
+    } else if length >= k {
+        length as i64
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    /// Resolves the start of each rectangle along one axis, where each either starts at 0
+    /// (`refs[i] == i`) or right after the rectangle `refs[i]`. Returns `None` for cyclic `refs`.
+    fn resolve(refs: &[usize], lengths: &[i32]) -> Option<Vec<i32>> {
+        let mut starts = vec![None; refs.len()];
+        for _ in 0..refs.len() {
+            for (i, &r) in refs.iter().enumerate() {
+                if r == i {
+                    starts[i] = Some(0);
+                } else if let Some(start) = starts[r] {
+                    starts[i] = Some(start + lengths[r]);
+                }
+            }
+        }
+
+        starts.into_iter().collect()
+    }
+
+    /// Returns the best objective over every packing that can't be pushed any further towards
+    /// the origin, which includes an optimal one.
+    fn brute_force(algorithm: &ExactPackingAlgorithm, rects: &[Rect]) -> i64 {
+        let n = rects.len();
+        let turns = if algorithm.allow_rotation { 1 << n } else { 1 };
+        let refs = |mut digits: usize| {
+            (0..n)
+                .map(|_| {
+                    let r = digits % n;
+                    digits /= n;
+                    r
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut best = i64::MAX;
+        for turn in 0..turns {
+            let (widths, heights): (Vec<_>, Vec<_>) = rects
+                .iter()
+                .enumerate()
+                .map(|(i, r)| match turn >> i & 1 {
+                    0 => (r.width(), r.height()),
+                    _ => (r.height(), r.width()),
+                })
+                .unzip();
+
+            for xs in 0..n.pow(n as u32) {
+                let Some(xs) = resolve(&refs(xs), &widths) else {
+                    continue;
+                };
+                let width = algorithm.width.unwrap_or(i32::MAX);
+                if (0..n).any(|i| xs[i] + widths[i] > width) {
+                    continue;
+                }
+
+                for ys in 0..n.pow(n as u32) {
+                    let Some(ys) = resolve(&refs(ys), &heights) else {
+                        continue;
+                    };
+                    let packing = (0..n)
+                        .map(|i| Rect {
+                            x1: xs[i],
+                            y1: ys[i],
+                            x2: xs[i] + widths[i],
+                            y2: ys[i] + heights[i],
+                        })
+                        .collect::<Vec<_>>();
+
+                    let disjoint =
+                        (0..n).all(|i| (0..i).all(|j| !packing[i].overlaps(&packing[j])));
+                    if disjoint {
+                        best = best.min(algorithm.objective(&packing));
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for width in [None, Some(5)] {
+            for allow_rotation in [false, true] {
+                let algorithm = ExactPackingAlgorithm {
+                    width,
+                    node_limit: None,
+                    time_limit: None,
+                    allow_rotation,
+                };
+
+                for _ in 0..10 {
+                    let rects = (0..rng.gen_range(2..=4))
+                        .map(|_| Rect {
+                            x1: 0,
+                            y1: 0,
+                            x2: rng.gen_range(1..=4),
+                            y2: rng.gen_range(1..=4),
+                        })
+                        .collect::<Vec<_>>();
+
+                    let mut packing = rects.clone();
+                    let outcome = algorithm.solve(&mut packing, &mut rng);
+                    let best = brute_force(&algorithm, &rects);
+
+                    assert_eq!(outcome.status, ExactStatus::Optimal);
+                    assert_eq!(outcome.lower_bound, best, "{rects:?}");
+                    assert_eq!(algorithm.objective(&packing), best, "{rects:?}");
+                    assert!(!Rect::match_items(&rects, &packing).contains(&usize::MAX));
+                    assert!(allow_rotation || !Rect::rotations(&rects, &packing).contains(&true));
+                    assert!(packing.iter().all(|p| p.x1 >= 0 && p.y1 >= 0));
+                    assert!(packing.iter().all(|p| p.x2 <= width.unwrap_or(i32::MAX)));
+                    for (i, p) in packing.iter().enumerate() {
+                        assert!(packing[..i].iter().all(|q| !p.overlaps(q)), "{packing:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cost_doesnt_grow_with_units() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let algorithm = ExactPackingAlgorithm {
+            width: None,
+            node_limit: None,
+            time_limit: None,
+            allow_rotation: true,
+        };
+
+        for _ in 0..5 {
+            let rects = (0..6)
+                .map(|_| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: rng.gen_range(1..=8),
+                    y2: rng.gen_range(1..=8),
+                })
+                .collect::<Vec<_>>();
+            // big enough that the areas of the bounding boxes considered don't fit in an i32
+            let scale = 2000;
+            let scaled = rects
+                .iter()
+                .map(|r| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: r.x2 * scale,
+                    y2: r.y2 * scale,
+                })
+                .collect::<Vec<_>>();
+
+            let outcome = algorithm.solve(&mut rects.clone(), &mut StdRng::seed_from_u64(0));
+            let mut packing = scaled.clone();
+            let scaled_outcome = algorithm.solve(&mut packing, &mut StdRng::seed_from_u64(0));
+
+            assert_eq!(scaled_outcome.status, ExactStatus::Optimal);
+            assert_eq!(scaled_outcome.nodes, outcome.nodes, "{rects:?}");
+            assert_eq!(
+                scaled_outcome.lower_bound,
+                outcome.lower_bound * i64::from(scale * scale)
+            );
+            assert!(!Rect::match_items(&scaled, &packing).contains(&usize::MAX));
+        }
+    }
+}
@@ -0,0 +1,499 @@
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use rayon::prelude::*;
+
+use crate::algorithms::anytime::{AnytimePacking, Budget};
+use crate::geometry::Rect;
+
+use crate::{
+    AnytimePackingAlgorithm, ObstaclePackingAlgorithm, PackingAlgorithm, PackingError,
+    PackingHeuristic, PackingHeuristicScore,
+};
+
+use super::skyline::{default_width, Skyline, SkylineRule};
+
+/// Evolves a population of [Chromosome]s, each decoded into a packing by placing its rectangles
+/// against a [Skyline] in order, and scored by the given heuristic.
+///
+/// Chromosomes are scored in parallel.
+pub struct GeneticPackingAlgorithm<H: PackingHeuristic> {
+    pub heuristic: H,
+    /// The width of the skyline used to decode chromosomes. If `None`, a roughly square width is
+    /// derived from the total area of the rectangles.
+    pub width: Option<i32>,
+    /// The number of chromosomes in each generation, treated as 1 if it is 0.
+    pub population_size: usize,
+    pub generations: usize,
+    pub crossover: Crossover,
+    /// The chance that each new chromosome is mutated, clamped to between 0 and 1.
+    pub mutation_rate: f64,
+    /// The number of best chromosomes carried over unchanged into each new generation.
+    pub elitism: usize,
+    /// The number of chromosomes competing in each tournament to be chosen as a parent.
+    pub tournament_size: usize,
+    /// Whether rectangles may be rotated by 90 degrees, as decided by each rectangle's [Gene].
+    pub allow_rotation: bool,
+}
+
+/// Decides how the insertion orders of two parents are combined.
+#[derive(Debug, Clone, Copy)]
+pub enum Crossover {
+    /// Order crossover (OX): a slice of one parent is kept in place, and the remaining positions
+    /// are filled with the other rectangles in the order they appear in the other parent.
+    Order,
+    /// Partially mapped crossover (PMX): a slice of one parent is kept in place, and the remaining
+    /// positions take the other parent's rectangles, mapped through the slice to avoid repeats.
+    PartiallyMapped,
+}
+
+/// A candidate solution: the order to place rectangles in, and how to place each one.
+#[derive(Debug, Clone)]
+pub struct Chromosome {
+    /// The indices of the rectangles in the order they are placed.
+    pub order: Vec<usize>,
+    /// The options for each rectangle, indexed like the rectangles rather than by `order`.
+    pub genes: Vec<Gene>,
+}
+
+/// The options for placing a single rectangle.
+#[derive(Debug, Clone, Copy)]
+pub struct Gene {
+    pub rule: SkylineRule,
+    /// Whether the rectangle is turned by 90 degrees. If it is then too wide for the skyline, it
+    /// is placed unrotated instead.
+    pub rotated: bool,
+}
+
+impl<H> PackingAlgorithm for GeneticPackingAlgorithm<H>
+where
+    H: PackingHeuristic + Sync,
+    H::Score: Send,
+{
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        let packing = self.pack_anytime(rects, &Budget::default(), rng)?;
+        Ok(packing.iterations as u64)
+    }
+}
+
+/// Chromosomes are decoded against a skyline raised by the obstacles, as in
+/// [SkylinePackingAlgorithm](super::skyline::SkylinePackingAlgorithm).
+impl<H> ObstaclePackingAlgorithm for GeneticPackingAlgorithm<H>
+where
+    H: PackingHeuristic + Sync,
+    H::Score: Send,
+{
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let packing = self.search(rects, obstacles, &Budget::default(), rng)?;
+        Ok(packing.iterations as u64)
+    }
+}
+
+/// Each generation counts as an iteration.
+impl<H> AnytimePackingAlgorithm for GeneticPackingAlgorithm<H>
+where
+    H: PackingHeuristic + Sync,
+    H::Score: Send,
+{
+    type Score = H::Score;
+
+    fn pack_anytime(
+        &self,
+        rects: &mut [Rect],
+        budget: &Budget,
+        rng: &mut dyn RngCore,
+    ) -> Result<AnytimePacking<H::Score>, PackingError> {
+        self.search(rects, &[], budget, rng)
+    }
+}
+
+impl<H> GeneticPackingAlgorithm<H>
+where
+    H: PackingHeuristic + Sync,
+    H::Score: Send,
+{
+    /// Runs the search described on [AnytimePackingAlgorithm::pack_anytime], placing the
+    /// rectangles around the `obstacles`.
+    fn search(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        budget: &Budget,
+        rng: &mut dyn RngCore,
+    ) -> Result<AnytimePacking<H::Score>, PackingError> {
+        let start = Instant::now();
+
+        let width = self
+            .width
+            .unwrap_or_else(|| default_width(rects, self.allow_rotation));
+        let fits = |r: &Rect| {
+            r.orientations(self.allow_rotation)
+                .any(|s| s.width <= width)
+        };
+        if let Some(item) = rects.iter().position(|r| !fits(r)) {
+            return Err(PackingError::Unplaceable { item });
+        }
+
+        let mut skyline = Skyline::new(width);
+        for obstacle in obstacles {
+            skyline.block(obstacle);
+        }
+
+        if rects.is_empty() {
+            return Ok(AnytimePacking {
+                timeline: vec![(start.elapsed(), self.heuristic.score(rects))],
+                iterations: 0,
+            });
+        }
+
+        let population_size = self.population_size.max(1);
+        let mut population = Vec::with_capacity(population_size);
+
+        // seed the population with the order a skyline packer would use
+        let mut by_height = (0..rects.len()).collect::<Vec<_>>();
+        by_height.sort_by_key(|&i| (-rects[i].height(), -rects[i].width()));
+        population.push(Chromosome {
+            order: by_height,
+            genes: vec![
+                Gene {
+                    rule: SkylineRule::BottomLeft,
+                    rotated: false,
+                };
+                rects.len()
+            ],
+        });
+
+        while population.len() < population_size {
+            population.push(Chromosome::random(rects.len(), self.allow_rotation, rng));
+        }
+
+        let mut scored = self.evaluate(rects, &skyline, population);
+        let mut timeline = vec![(start.elapsed(), self.best_score(rects, &skyline, &scored))];
+        // without elitism the best chromosome can be lost, so it's kept aside
+        let mut best = scored[0].0.clone();
+
+        // a NaN rate fails the comparison, so it never mutates
+        let mutation_rate = if self.mutation_rate > 0.0 {
+            self.mutation_rate.min(1.0)
+        } else {
+            0.0
+        };
+
+        let mut iterations = 0;
+        for generation in 0..self.generations {
+            if budget.is_exhausted(generation) {
+                break;
+            }
+            iterations += 1;
+
+            let mut next = scored
+                .iter()
+                .take(self.elitism)
+                .map(|(c, _)| c.clone())
+                .collect::<Vec<_>>();
+
+            while next.len() < population_size {
+                let a = self.select(&scored, rng);
+                let b = self.select(&scored, rng);
+                let mut child = a.crossover(b, self.crossover, rng);
+                if rng.gen_bool(mutation_rate) {
+                    child.mutate(self.allow_rotation, rng);
+                }
+                next.push(child);
+            }
+
+            scored = self.evaluate(rects, &skyline, next);
+
+            let (_, best_score) = timeline.last().unwrap();
+            if scored[0].1.is_better_than(best_score) {
+                best = scored[0].0.clone();
+                timeline.push((start.elapsed(), self.best_score(rects, &skyline, &scored)));
+            }
+        }
+
+        best.decode(rects, &skyline);
+
+        Ok(AnytimePacking {
+            timeline,
+            iterations,
+        })
+    }
+
+    /// Scores each chromosome in parallel, returning them sorted from best to worst.
+    fn evaluate(
+        &self,
+        rects: &[Rect],
+        skyline: &Skyline,
+        population: Vec<Chromosome>,
+    ) -> Vec<(Chromosome, H::Score)> {
+        let mut scored = population
+            .into_par_iter()
+            .map(|chromosome| {
+                let mut packing = rects.to_vec();
+                chromosome.decode(&mut packing, skyline);
+                let score = self.heuristic.score(&packing);
+                (chromosome, score)
+            })
+            .collect::<Vec<_>>();
+
+        scored.sort_by(|(_, a), (_, b)| a.cmp_better(b));
+
+        scored
+    }
+
+    /// Scores the best chromosome of a population sorted from best to worst again, since scores
+    /// can't be copied.
+    fn best_score(
+        &self,
+        rects: &[Rect],
+        skyline: &Skyline,
+        scored: &[(Chromosome, H::Score)],
+    ) -> H::Score {
+        let mut packing = rects.to_vec();
+        scored[0].0.decode(&mut packing, skyline);
+        self.heuristic.score(&packing)
+    }
+
+    /// Chooses a parent by tournament selection from a population sorted from best to worst.
+    fn select<'a>(
+        &self,
+        scored: &'a [(Chromosome, H::Score)],
+        rng: &mut (impl Rng + ?Sized),
+    ) -> &'a Chromosome {
+        let winner = (0..self.tournament_size.max(1))
+            .map(|_| rng.gen_range(0..scored.len()))
+            .min()
+            .unwrap();
+
+        &scored[winner].0
+    }
+}
+
+impl Chromosome {
+    /// Creates a chromosome with a random order and random genes for `n` rectangles, which are only
+    /// rotated if `allow_rotation` is true.
+    pub fn random(n: usize, allow_rotation: bool, rng: &mut (impl Rng + ?Sized)) -> Chromosome {
+        let mut order = (0..n).collect::<Vec<_>>();
+        order.shuffle(rng);
+
+        Chromosome {
+            order,
+            genes: (0..n).map(|_| Gene::random(allow_rotation, rng)).collect(),
+        }
+    }
+
+    /// Places the `rects` against a copy of `skyline` in this chromosome's order. Every rectangle
+    /// must fit within the width of `skyline` in at least one orientation.
+    pub fn decode(&self, rects: &mut [Rect], skyline: &Skyline) {
+        let width = skyline.width();
+        let mut skyline = skyline.clone();
+
+        for &i in self.order.iter() {
+            let gene = &self.genes[i];
+            let rect = &mut rects[i];
+
+            let (w, h) = if gene.rotated && rect.height() <= width {
+                (rect.height(), rect.width())
+            } else if rect.width() <= width {
+                (rect.width(), rect.height())
+            } else {
+                (rect.height(), rect.width())
+            };
+
+            *rect = skyline.place(w, h, gene.rule, false).unwrap();
+        }
+    }
+
+    /// Combines this chromosome with `other`. Each gene is taken from either parent at random.
+    pub fn crossover(
+        &self,
+        other: &Chromosome,
+        kind: Crossover,
+        rng: &mut (impl Rng + ?Sized),
+    ) -> Chromosome {
+        let n = self.order.len();
+        let start = rng.gen_range(0..n);
+        let end = rng.gen_range(start..=n);
+
+        let order = match kind {
+            Crossover::Order => order_crossover(&self.order, &other.order, start, end),
+            Crossover::PartiallyMapped => {
+                partially_mapped_crossover(&self.order, &other.order, start, end)
+            }
+        };
+
+        let genes = self
+            .genes
+            .iter()
+            .zip(other.genes.iter())
+            .map(|(a, b)| if rng.gen_bool(0.5) { *a } else { *b })
+            .collect();
+
+        Chromosome { order, genes }
+    }
+
+    /// Swaps two rectangles in the order and re-randomizes one rectangle's gene.
+    pub fn mutate(&mut self, allow_rotation: bool, rng: &mut (impl Rng + ?Sized)) {
+        let n = self.order.len();
+        self.order.swap(rng.gen_range(0..n), rng.gen_range(0..n));
+        self.genes[rng.gen_range(0..n)] = Gene::random(allow_rotation, rng);
+    }
+}
+
+impl Gene {
+    fn random(allow_rotation: bool, rng: &mut (impl Rng + ?Sized)) -> Gene {
+        Gene {
+            rule: if rng.gen_bool(0.5) {
+                SkylineRule::BottomLeft
+            } else {
+                SkylineRule::MinWaste
+            },
+            rotated: allow_rotation && rng.gen_bool(0.5),
+        }
+    }
+}
+
+fn order_crossover(a: &[usize], b: &[usize], start: usize, end: usize) -> Vec<usize> {
+    let mut taken = vec![false; a.len()];
+    for &i in &a[start..end] {
+        taken[i] = true;
+    }
+
+    let mut rest = b.iter().copied().filter(|&i| !taken[i]);
+
+    let mut child = Vec::with_capacity(a.len());
+    child.extend(rest.by_ref().take(start));
+    child.extend_from_slice(&a[start..end]);
+    child.extend(rest);
+    child
+}
+
+fn partially_mapped_crossover(a: &[usize], b: &[usize], start: usize, end: usize) -> Vec<usize> {
+    // where each rectangle sits in `a`, so that conflicts can be mapped through the kept slice
+    let mut position_in_a = vec![0; a.len()];
+    for (p, &i) in a.iter().enumerate() {
+        position_in_a[i] = p;
+    }
+
+    let mut child = b.to_vec();
+    child[start..end].copy_from_slice(&a[start..end]);
+
+    for p in (0..start).chain(end..a.len()) {
+        let mut i = b[p];
+        while (start..end).contains(&position_in_a[i]) {
+            i = b[position_in_a[i]];
+        }
+        child[p] = i;
+    }
+
+    child
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::heuristics::score::{score, ScorePackingHeuristic};
+
+    use super::*;
+
+    #[test]
+    fn returns_best_packing_without_elitism() {
+        let algorithm = GeneticPackingAlgorithm {
+            heuristic: ScorePackingHeuristic,
+            width: None,
+            population_size: 10,
+            generations: 30,
+            crossover: Crossover::Order,
+            mutation_rate: 0.3,
+            elitism: 0,
+            tournament_size: 3,
+            allow_rotation: true,
+        };
+
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut rects = (0..15)
+                .map(|_| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: rng.gen_range(1..=10),
+                    y2: rng.gen_range(1..=10),
+                })
+                .collect::<Vec<_>>();
+
+            let packing = algorithm
+                .pack_anytime(&mut rects, &Budget::default(), &mut rng)
+                .unwrap();
+
+            let (_, best) = packing.timeline.last().unwrap();
+            assert_eq!(*best, score(&rects), "seed {seed}");
+            assert!(packing.timeline.iter().all(|(_, s)| s >= best));
+        }
+    }
+
+    #[test]
+    fn empty_population_is_treated_as_one() {
+        let algorithm = GeneticPackingAlgorithm {
+            heuristic: ScorePackingHeuristic,
+            width: None,
+            population_size: 0,
+            generations: 5,
+            crossover: Crossover::PartiallyMapped,
+            mutation_rate: 0.5,
+            elitism: 0,
+            tournament_size: 3,
+            allow_rotation: false,
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for n in [0, 1, 6] {
+            let mut rects = (0..n)
+                .map(|i| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: i + 1,
+                    y2: 3,
+                })
+                .collect::<Vec<_>>();
+
+            assert!(algorithm.pack(&mut rects, &mut rng).is_ok());
+        }
+    }
+
+    #[test]
+    fn mutation_rate_is_clamped() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for mutation_rate in [-1.0, 2.0, f64::NAN] {
+            let algorithm = GeneticPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                width: None,
+                population_size: 4,
+                generations: 3,
+                crossover: Crossover::Order,
+                mutation_rate,
+                elitism: 1,
+                tournament_size: 2,
+                allow_rotation: true,
+            };
+            let mut rects = (0..5)
+                .map(|i| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: i + 1,
+                    y2: 2,
+                })
+                .collect::<Vec<_>>();
+
+            assert!(algorithm.pack(&mut rects, &mut rng).is_ok());
+        }
+    }
+}
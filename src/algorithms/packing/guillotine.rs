@@ -0,0 +1,377 @@
+use rand::RngCore;
+
+use crate::geometry::Rect;
+
+use crate::{ObstaclePackingAlgorithm, PackingAlgorithm, PackingError};
+
+use super::skyline::{default_width, stacked_height};
+
+/// Places rectangles into a set of disjoint free rectangles, splitting the free rectangle used by
+/// each placement in two with a single edge-to-edge cut.
+///
+/// Every packing produced this way can be cut apart with a sequence of guillotine cuts.
+pub struct GuillotinePackingAlgorithm {
+    /// The width of the container. If `None`, a roughly square width is derived from the total
+    /// area of the rectangles.
+    pub width: Option<i32>,
+    pub fit: GuillotineFit,
+    pub split: GuillotineSplit,
+    /// Whether to merge the free rectangles left over by each placement when they form a single
+    /// rectangle. Only the pieces of the same split are merged, so the packing stays guillotine.
+    pub merge: bool,
+    /// Whether rectangles may be rotated by 90 degrees to fit better.
+    pub allow_rotation: bool,
+}
+
+/// Decides which free rectangle a rectangle is placed in.
+#[derive(Debug, Clone, Copy)]
+pub enum GuillotineFit {
+    BestArea,
+    BestShortSide,
+    BestLongSide,
+    WorstArea,
+    WorstShortSide,
+    WorstLongSide,
+}
+
+/// Decides which way the leftover space of a free rectangle is cut after a placement.
+#[derive(Debug, Clone, Copy)]
+pub enum GuillotineSplit {
+    /// Cut along the shorter of the two leftover sides.
+    ShorterLeftoverAxis,
+    /// Cut along the longer of the two leftover sides.
+    LongerLeftoverAxis,
+    /// Cut along the shorter side of the free rectangle.
+    ShorterAxis,
+    /// Cut along the longer side of the free rectangle.
+    LongerAxis,
+    /// Cut so that the smaller of the two resulting free rectangles is as small as possible.
+    MinArea,
+    /// Cut so that the smaller of the two resulting free rectangles is as large as possible.
+    MaxArea,
+}
+
+impl PackingAlgorithm for GuillotinePackingAlgorithm {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.try_pack_around(rects, &[], rng)
+    }
+}
+
+/// The obstacles are cut out of the free rectangles with guillotine cuts before any rectangle is
+/// placed, so the packing stays guillotine along with any obstacles that don't overlap each other.
+impl ObstaclePackingAlgorithm for GuillotinePackingAlgorithm {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        _rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        // there is always room below the lowest obstacle
+        let bounds = Rect {
+            x1: 0,
+            y1: 0,
+            x2: self
+                .width
+                .unwrap_or_else(|| default_width(rects, self.allow_rotation)),
+            y2: stacked_height(rects, self.allow_rotation)
+                + obstacles.iter().map(|o| o.y2).max().unwrap_or(0).max(0),
+        };
+
+        let mut free = vec![bounds.clone()];
+        for obstacle in obstacles.iter().filter(|o| !o.is_empty()) {
+            cut_obstacle(&mut free, obstacle);
+        }
+
+        for i in 0..rects.len() {
+            let free_ref = &free;
+            let best = rects[i..]
+                .iter()
+                .enumerate()
+                .flat_map(|(j, rect)| {
+                    rect.orientations(self.allow_rotation)
+                        .flat_map(move |size| {
+                            free_ref
+                                .iter()
+                                .enumerate()
+                                .filter(move |(_, free_rect)| {
+                                    free_rect.width() >= size.width
+                                        && free_rect.height() >= size.height
+                                })
+                                .map(move |(k, free_rect)| {
+                                    let placed = Rect {
+                                        x1: free_rect.x1,
+                                        y1: free_rect.y1,
+                                        x2: free_rect.x1 + size.width,
+                                        y2: free_rect.y1 + size.height,
+                                    };
+
+                                    let key = self.score(free_rect, &placed);
+
+                                    (i + j, k, placed, key)
+                                })
+                        })
+                })
+                .min_by_key(|(_, _, _, key)| *key);
+
+            let Some((j, k, placed, _)) = best else {
+                return Err(PackingError::Unplaceable { item: i });
+            };
+
+            let free_rect = free.swap_remove(k);
+
+            rects.swap(i, j);
+            rects[i] = placed;
+
+            let mut pieces = self
+                .split(&bounds, &free_rect, &rects[i])
+                .collect::<Vec<_>>();
+            if self.merge {
+                // the pieces are siblings under the same cut, so merging them can't break it
+                Rect::simplify(&mut pieces);
+            }
+            free.extend(pieces);
+        }
+
+        Ok(rects.len() as u64)
+    }
+}
+
+impl GuillotinePackingAlgorithm {
+    /// Scores placing a rectangle at `rect` within `free_rect`. Lower scores are better.
+    fn score(&self, free_rect: &Rect, rect: &Rect) -> (i32, i32) {
+        let leftover_horz = free_rect.width() - rect.width();
+        let leftover_vert = free_rect.height() - rect.height();
+        let short_side = leftover_horz.min(leftover_vert);
+        let long_side = leftover_horz.max(leftover_vert);
+        let area = free_rect.area() - rect.area();
+
+        match self.fit {
+            GuillotineFit::BestArea => (area, short_side),
+            GuillotineFit::BestShortSide => (short_side, long_side),
+            GuillotineFit::BestLongSide => (long_side, short_side),
+            GuillotineFit::WorstArea => (-area, -short_side),
+            GuillotineFit::WorstShortSide => (-short_side, -long_side),
+            GuillotineFit::WorstLongSide => (-long_side, -short_side),
+        }
+    }
+
+    /// Splits the space left over in `free_rect` after placing `placed` in its corner into up to
+    /// two free rectangles.
+    ///
+    /// The free rectangle at the far `y2` end of `bounds` is always cut horizontally, so that it
+    /// keeps spanning the full width and there is always room for another rectangle.
+    fn split(&self, bounds: &Rect, free_rect: &Rect, placed: &Rect) -> impl Iterator<Item = Rect> {
+        let leftover_horz = free_rect.x2 - placed.x2;
+        let leftover_vert = free_rect.y2 - placed.y2;
+
+        let horizontal = free_rect.y2 == bounds.y2
+            || match self.split {
+                GuillotineSplit::ShorterLeftoverAxis => leftover_horz <= leftover_vert,
+                GuillotineSplit::LongerLeftoverAxis => leftover_horz > leftover_vert,
+                GuillotineSplit::ShorterAxis => free_rect.width() <= free_rect.height(),
+                GuillotineSplit::LongerAxis => free_rect.width() > free_rect.height(),
+                GuillotineSplit::MinArea => {
+                    placed.width() * leftover_vert > leftover_horz * placed.height()
+                }
+                GuillotineSplit::MaxArea => {
+                    placed.width() * leftover_vert <= leftover_horz * placed.height()
+                }
+            };
+
+        let (side, above) = if horizontal {
+            // the cut runs horizontally along the placed rectangle's bottom edge
+            (
+                Rect {
+                    x1: placed.x2,
+                    y1: free_rect.y1,
+                    x2: free_rect.x2,
+                    y2: placed.y2,
+                },
+                Rect {
+                    x1: free_rect.x1,
+                    y1: placed.y2,
+                    x2: free_rect.x2,
+                    y2: free_rect.y2,
+                },
+            )
+        } else {
+            // the cut runs vertically along the placed rectangle's right edge
+            (
+                Rect {
+                    x1: placed.x2,
+                    y1: free_rect.y1,
+                    x2: free_rect.x2,
+                    y2: free_rect.y2,
+                },
+                Rect {
+                    x1: free_rect.x1,
+                    y1: placed.y2,
+                    x2: placed.x2,
+                    y2: free_rect.y2,
+                },
+            )
+        };
+
+        [side, above].into_iter().filter(|r| !r.is_empty())
+    }
+}
+
+/// Cuts `obstacle` out of every free rectangle it overlaps, first across the free rectangle's
+/// full width above and below the obstacle, then beside it.
+fn cut_obstacle(free: &mut Vec<Rect>, obstacle: &Rect) {
+    let mut i = 0;
+    while i < free.len() {
+        if !free[i].overlaps(obstacle) {
+            i += 1;
+            continue;
+        }
+
+        let free_rect = free.swap_remove(i);
+        let (y1, y2) = (obstacle.y1.max(free_rect.y1), obstacle.y2.min(free_rect.y2));
+        let pieces = [
+            Rect {
+                y2: y1,
+                ..free_rect.clone()
+            },
+            Rect {
+                y1: y2,
+                ..free_rect.clone()
+            },
+            Rect {
+                x2: obstacle.x1.max(free_rect.x1),
+                y1,
+                y2,
+                ..free_rect.clone()
+            },
+            Rect {
+                x1: obstacle.x2.min(free_rect.x2),
+                y1,
+                y2,
+                ..free_rect.clone()
+            },
+        ];
+
+        // the pieces don't overlap the obstacle, so the loop passes over them
+        free.extend(pieces.into_iter().filter(|r| !r.is_empty()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const FITS: [GuillotineFit; 6] = [
+        GuillotineFit::BestArea,
+        GuillotineFit::BestShortSide,
+        GuillotineFit::BestLongSide,
+        GuillotineFit::WorstArea,
+        GuillotineFit::WorstShortSide,
+        GuillotineFit::WorstLongSide,
+    ];
+
+    const SPLITS: [GuillotineSplit; 6] = [
+        GuillotineSplit::ShorterLeftoverAxis,
+        GuillotineSplit::LongerLeftoverAxis,
+        GuillotineSplit::ShorterAxis,
+        GuillotineSplit::LongerAxis,
+        GuillotineSplit::MinArea,
+        GuillotineSplit::MaxArea,
+    ];
+
+    /// Four rectangles wound around a hole in the middle, which no single cut can separate.
+    fn pinwheel() -> Vec<Rect> {
+        vec![
+            Rect {
+                x1: 0,
+                y1: 0,
+                x2: 4,
+                y2: 1,
+            },
+            Rect {
+                x1: 4,
+                y1: 0,
+                x2: 5,
+                y2: 4,
+            },
+            Rect {
+                x1: 1,
+                y1: 4,
+                x2: 5,
+                y2: 5,
+            },
+            Rect {
+                x1: 0,
+                y1: 1,
+                x2: 1,
+                y2: 5,
+            },
+        ]
+    }
+
+    #[test]
+    fn packings_are_guillotine() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for fit in FITS {
+            for split in SPLITS {
+                for merge in [false, true] {
+                    for allow_rotation in [false, true] {
+                        let algorithm = GuillotinePackingAlgorithm {
+                            width: Some(20),
+                            fit,
+                            split,
+                            merge,
+                            allow_rotation,
+                        };
+
+                        for _ in 0..5 {
+                            let mut rects = (0..rng.gen_range(2..=15))
+                                .map(|_| Rect {
+                                    x1: 0,
+                                    y1: 0,
+                                    x2: rng.gen_range(1..=10),
+                                    y2: rng.gen_range(1..=10),
+                                })
+                                .collect::<Vec<_>>();
+
+                            algorithm.try_pack(&mut rects, &mut rng).unwrap();
+
+                            assert!(rects.iter().all(|r| r.x1 >= 0 && r.y1 >= 0 && r.x2 <= 20));
+                            for (i, r) in rects.iter().enumerate() {
+                                assert!(rects[..i].iter().all(|q| !r.overlaps(q)), "{rects:?}");
+                            }
+                            assert!(Rect::is_guillotine(&rects), "{fit:?} {split:?} {rects:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pinwheel_is_not_guillotine() {
+        let mut rects = pinwheel();
+        assert!(!Rect::is_guillotine(&rects));
+
+        rects.push(Rect {
+            x1: 1,
+            y1: 1,
+            x2: 4,
+            y2: 4,
+        });
+        assert!(!Rect::is_guillotine(&rects));
+
+        // splitting one arm opens up a cut along the whole layout
+        rects[0].x2 = 1;
+        rects.push(Rect {
+            x1: 1,
+            y1: 0,
+            x2: 4,
+            y2: 1,
+        });
+        assert!(Rect::is_guillotine(&rects));
+    }
+}
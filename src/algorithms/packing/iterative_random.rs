@@ -1,26 +1,83 @@
 use std::mem::swap;
+use std::time::Instant;
 
-use rand::Rng;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
 
+use crate::algorithms::anytime::{AnytimePacking, Budget};
 use crate::geometry::Rect;
 
-use crate::{PackingAlgorithm, PackingHeuristic, PackingHeuristicScore};
+use crate::{
+    AnytimePackingAlgorithm, ObstaclePackingAlgorithm, PackingAlgorithm, PackingError,
+    PackingHeuristic, PackingHeuristicScore,
+};
+
+use super::random::{fitting_sizes, outer_bounds};
 
 pub struct IterativeRandomPackingAlgorithm<H: PackingHeuristic> {
     pub heuristic: H,
     pub trials_per_iteration: usize,
+    /// The width of the strip to pack into. If `None`, rectangles are scattered over a region as
+    /// wide as all of them side by side.
+    pub width: Option<i32>,
+    /// Whether rectangles may be rotated by 90 degrees, which each trial is with even chance.
+    pub allow_rotation: bool,
 }
 
 impl<H: PackingHeuristic> PackingAlgorithm for IterativeRandomPackingAlgorithm<H> {
-    fn pack(&self, rects: &mut [Rect]) -> bool {
-        let mut rng = rand::thread_rng();
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        let packing = self.pack_anytime(rects, &Budget::default(), rng)?;
+        Ok(packing.iterations as u64)
+    }
+}
 
-        let outer_bounds = Rect {
-            x1: 0,
-            y1: 0,
-            x2: rects.iter().map(|r| r.x2 - r.x1).sum::<i32>(),
-            y2: rects.iter().map(|r| r.y2 - r.y1).sum::<i32>(),
-        };
+/// Trials that overlap an obstacle are skipped like those that overlap another rectangle.
+impl<H: PackingHeuristic> ObstaclePackingAlgorithm for IterativeRandomPackingAlgorithm<H> {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let packing = self.search(rects, obstacles, &Budget::default(), rng)?;
+        Ok(packing.iterations as u64)
+    }
+}
+
+/// Each trial counts as an iteration. Only trials for the last rectangle complete a packing, so
+/// the timeline follows the improvements among those. Once the budget runs out, each remaining
+/// rectangle is placed at the first position tried that doesn't overlap the others, so that a
+/// packing can still be returned, and if some rectangle can't be placed then, the search fails
+/// with [PackingError::BudgetExhausted].
+impl<H: PackingHeuristic> AnytimePackingAlgorithm for IterativeRandomPackingAlgorithm<H> {
+    type Score = H::Score;
+
+    fn pack_anytime(
+        &self,
+        rects: &mut [Rect],
+        budget: &Budget,
+        rng: &mut dyn RngCore,
+    ) -> Result<AnytimePacking<H::Score>, PackingError> {
+        self.search(rects, &[], budget, rng)
+    }
+}
+
+impl<H: PackingHeuristic> IterativeRandomPackingAlgorithm<H> {
+    /// Runs the search described on [AnytimePackingAlgorithm::pack_anytime], placing the
+    /// rectangles around the `obstacles`.
+    fn search(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        budget: &Budget,
+        rng: &mut dyn RngCore,
+    ) -> Result<AnytimePacking<H::Score>, PackingError> {
+        let start = Instant::now();
+        let input = rects.to_vec();
+        let mut iterations = 0;
+        let mut timeline = vec![];
+
+        let outer_bounds = outer_bounds(rects, obstacles, self.width, self.allow_rotation);
 
         for i in 0..rects.len() {
             let mut best_score = None;
@@ -33,23 +90,34 @@ impl<H: PackingHeuristic> PackingAlgorithm for IterativeRandomPackingAlgorithm<H
                 .unwrap()
                 .0;
 
+            // the chosen rectangle is placed at `i`, so each trial only needs to replace `rects[i]`
+            rects.swap(i, rect_index);
+
+            let sizes = fitting_sizes(&outer_bounds, &rects[i], self.allow_rotation);
+            if sizes.is_empty() {
+                return Err(PackingError::Unplaceable { item: i }.restoring(&input, rects));
+            }
+
             for _ in 0..self.trials_per_iteration {
-                let rect = &rects[rect_index];
+                if best_score.is_some() && budget.is_exhausted(iterations) {
+                    break;
+                }
+                iterations += 1;
 
-                let width = rect.x2 - rect.x1;
-                let height = rect.y2 - rect.y1;
-                let x1 = rng.gen_range(outer_bounds.x1..outer_bounds.x2 - width);
-                let y1 = rng.gen_range(outer_bounds.y1..outer_bounds.y2 - height);
+                let size = sizes.choose(rng).unwrap();
+                let x1 = rng.gen_range(outer_bounds.x1..=outer_bounds.x2 - size.width);
+                let y1 = rng.gen_range(outer_bounds.y1..=outer_bounds.y2 - size.height);
 
                 let mut rect = Rect {
                     x1,
                     y1,
-                    x2: x1 + width,
-                    y2: y1 + height,
+                    x2: x1 + size.width,
+                    y2: y1 + size.height,
                 };
 
-                if rects[..i].iter().any(|p| rect.overlaps(p)) {
-                    // if this rectangle overlaps any of the existing ones, we won't consider it
+                if rects[..i].iter().chain(obstacles).any(|p| rect.overlaps(p)) {
+                    // if this rectangle overlaps any of the existing ones or an obstacle, we won't
+                    // consider it
                     continue;
                 }
 
@@ -57,28 +125,38 @@ impl<H: PackingHeuristic> PackingAlgorithm for IterativeRandomPackingAlgorithm<H
 
                 let score = self.heuristic.score(&rects[..=i]);
 
-                if let Some(best_score) = &mut best_score {
-                    if score.is_better_than(best_score) {
-                        *best_score = score;
-                        if rect_index != i {
-                            rects[rect_index] = rect;
-                        }
-                    } else {
-                        swap(&mut rect, &mut rects[i]);
-                    }
-                } else {
+                if best_score
+                    .as_ref()
+                    .is_none_or(|best| score.is_better_than(best))
+                {
                     best_score = Some(score);
-                    if rect_index != i {
-                        rects[rect_index] = rect;
+
+                    if i + 1 == rects.len() {
+                        timeline.push((start.elapsed(), self.heuristic.score(rects)));
                     }
+                } else {
+                    swap(&mut rect, &mut rects[i]);
                 }
             }
 
+            // give up if no trial found a place for this rectangle
             if best_score.is_none() {
-                return false;
+                let err = if budget.is_exhausted(iterations) {
+                    PackingError::BudgetExhausted
+                } else {
+                    PackingError::Unplaceable { item: i }
+                };
+                return Err(err.restoring(&input, rects));
             }
         }
 
-        true
+        if timeline.is_empty() {
+            timeline.push((start.elapsed(), self.heuristic.score(rects)));
+        }
+
+        Ok(AnytimePacking {
+            timeline,
+            iterations,
+        })
     }
 }
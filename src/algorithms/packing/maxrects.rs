@@ -0,0 +1,251 @@
+use rand::RngCore;
+
+use crate::geometry::Rect;
+
+use crate::{ObstaclePackingAlgorithm, PackingAlgorithm, PackingError};
+
+use super::skyline::{default_width, stacked_height};
+
+/// Places rectangles into the set of maximal free rectangles left over by the rectangles placed so
+/// far, choosing both the next rectangle and its position according to a [MaxRectsRule].
+pub struct MaxRectsPackingAlgorithm {
+    /// The width of the container. If `None`, a roughly square width is derived from the total
+    /// area of the rectangles.
+    pub width: Option<i32>,
+    pub rule: MaxRectsRule,
+    /// Whether rectangles may be rotated by 90 degrees to fit better.
+    pub allow_rotation: bool,
+}
+
+/// Decides which free rectangle a rectangle is placed in.
+#[derive(Debug, Clone, Copy)]
+pub enum MaxRectsRule {
+    /// Minimize the shorter of the two leftover sides of the free rectangle.
+    BestShortSideFit,
+    /// Minimize the longer of the two leftover sides of the free rectangle.
+    BestLongSideFit,
+    /// Minimize the leftover area of the free rectangle.
+    BestAreaFit,
+    /// Minimize the `y2` edge of the placed rectangle, breaking ties by the leftmost position.
+    BottomLeft,
+    /// Maximize the length of the placed rectangle's perimeter that touches other rectangles or
+    /// the container's edges.
+    ContactPoint,
+}
+
+impl PackingAlgorithm for MaxRectsPackingAlgorithm {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.try_pack_around(rects, &[], rng)
+    }
+}
+
+/// The obstacles are cut out of the free rectangles before any rectangle is placed, and count as
+/// neighbors under [MaxRectsRule::ContactPoint].
+impl ObstaclePackingAlgorithm for MaxRectsPackingAlgorithm {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        _rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        // there is always room below the lowest obstacle
+        let bounds = Rect {
+            x1: 0,
+            y1: 0,
+            x2: self
+                .width
+                .unwrap_or_else(|| default_width(rects, self.allow_rotation)),
+            y2: stacked_height(rects, self.allow_rotation)
+                + obstacles.iter().map(|o| o.y2).max().unwrap_or(0).max(0),
+        };
+
+        let mut free = vec![bounds.clone()];
+        for obstacle in obstacles.iter().filter(|o| !o.is_empty()) {
+            place(&mut free, obstacle);
+        }
+        let bounds = &bounds;
+
+        for i in 0..rects.len() {
+            let (packing, choices) = rects.split_at(i);
+            let free_ref = &free;
+            let best = choices
+                .iter()
+                .enumerate()
+                .flat_map(|(j, rect)| {
+                    rect.orientations(self.allow_rotation)
+                        .flat_map(move |size| {
+                            free_ref
+                                .iter()
+                                .filter(move |free_rect| {
+                                    free_rect.width() >= size.width
+                                        && free_rect.height() >= size.height
+                                })
+                                .map(move |free_rect| {
+                                    let placed = Rect {
+                                        x1: free_rect.x1,
+                                        y1: free_rect.y1,
+                                        x2: free_rect.x1 + size.width,
+                                        y2: free_rect.y1 + size.height,
+                                    };
+
+                                    let key =
+                                        self.score(bounds, obstacles, packing, free_rect, &placed);
+
+                                    (i + j, placed, key)
+                                })
+                        })
+                })
+                .min_by_key(|(_, _, key)| *key);
+
+            let Some((j, placed, _)) = best else {
+                return Err(PackingError::Unplaceable { item: i });
+            };
+
+            rects.swap(i, j);
+            rects[i] = placed;
+
+            place(&mut free, &rects[i]);
+        }
+
+        Ok(rects.len() as u64)
+    }
+}
+
+impl MaxRectsPackingAlgorithm {
+    /// Scores placing a rectangle at `placed` within `free_rect`. Lower scores are better.
+    fn score(
+        &self,
+        bounds: &Rect,
+        obstacles: &[Rect],
+        packing: &[Rect],
+        free_rect: &Rect,
+        placed: &Rect,
+    ) -> (i32, i32) {
+        let leftover_horz = free_rect.width() - placed.width();
+        let leftover_vert = free_rect.height() - placed.height();
+        let short_side = leftover_horz.min(leftover_vert);
+        let long_side = leftover_horz.max(leftover_vert);
+
+        match self.rule {
+            MaxRectsRule::BestShortSideFit => (short_side, long_side),
+            MaxRectsRule::BestLongSideFit => (long_side, short_side),
+            MaxRectsRule::BestAreaFit => (free_rect.area() - placed.area(), short_side),
+            MaxRectsRule::BottomLeft => (placed.y2, placed.x1),
+            MaxRectsRule::ContactPoint => (
+                -contact_score(bounds, obstacles, packing, placed),
+                placed.y2,
+            ),
+        }
+    }
+}
+
+/// Splits every free rectangle that `placed` overlaps into the maximal rectangles around it, then
+/// prunes any free rectangle contained within another.
+fn place(free: &mut Vec<Rect>, placed: &Rect) {
+    let mut i = 0;
+    while i < free.len() {
+        if free[i].overlaps(placed) {
+            let free_rect = free.swap_remove(i);
+            free.extend(free_rect.cut_out_maximal(placed));
+        } else {
+            i += 1;
+        }
+    }
+
+    let mut i = 0;
+    while i < free.len() {
+        let contained = free.iter().enumerate().any(|(j, other)| {
+            j != i && other.contains(&free[i]) && (j < i || !free[i].contains(other))
+        });
+
+        if contained {
+            free.swap_remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Calculates the total length of `rect`'s perimeter that touches either the `obstacles`, the
+/// rectangles in `packing` or the edges of `bounds`.
+fn contact_score(bounds: &Rect, obstacles: &[Rect], packing: &[Rect], rect: &Rect) -> i32 {
+    let walls = [
+        Rect {
+            x1: bounds.x1 - 1,
+            x2: bounds.x1,
+            ..bounds.clone()
+        },
+        Rect {
+            y1: bounds.y1 - 1,
+            y2: bounds.y1,
+            ..bounds.clone()
+        },
+        Rect {
+            x1: bounds.x2,
+            x2: bounds.x2 + 1,
+            ..bounds.clone()
+        },
+    ];
+
+    let mut acc = 0;
+    for other in obstacles.iter().chain(packing).chain(walls.iter()) {
+        if let Some((_, amt)) = rect.amount_touching(other) {
+            acc += amt;
+        }
+    }
+    acc
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn packings_are_valid() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for rule in [
+            MaxRectsRule::BestShortSideFit,
+            MaxRectsRule::BestLongSideFit,
+            MaxRectsRule::BestAreaFit,
+            MaxRectsRule::BottomLeft,
+            MaxRectsRule::ContactPoint,
+        ] {
+            for allow_rotation in [false, true] {
+                for width in [None, Some(20)] {
+                    let algorithm = MaxRectsPackingAlgorithm {
+                        width,
+                        rule,
+                        allow_rotation,
+                    };
+
+                    for _ in 0..5 {
+                        let input = (0..rng.gen_range(2..=15))
+                            .map(|_| Rect {
+                                x1: 0,
+                                y1: 0,
+                                x2: rng.gen_range(1..=10),
+                                y2: rng.gen_range(1..=10),
+                            })
+                            .collect::<Vec<_>>();
+                        let mut rects = input.clone();
+
+                        algorithm.pack(&mut rects, &mut rng).unwrap();
+
+                        assert!(!Rect::match_items(&input, &rects).contains(&usize::MAX));
+                        assert!(rects.iter().all(|r| r.x1 >= 0 && r.y1 >= 0), "{rects:?}");
+                        if let Some(width) = width {
+                            assert!(rects.iter().all(|r| r.x2 <= width), "{rule:?} {rects:?}");
+                        }
+                        for (i, r) in rects.iter().enumerate() {
+                            assert!(rects[..i].iter().all(|q| !r.overlaps(q)), "{rects:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use std::ops::Deref;
+
+use neun::{Model, ModelDriver};
+use rand::RngCore;
+
+use crate::geometry::Rect;
+use crate::nn::{devectorize_position, position_count, reward, vectorize_input};
+
+use crate::{PackingAlgorithm, PackingError};
+
+/// Places each rectangle in turn at the position chosen by a Monte Carlo tree search, which uses
+/// the network's output as a prior over positions and the training reward to value packings.
+///
+/// Rectangles are placed in the order given, within `bounds`, the same as when the network is
+/// used directly. The search's [policy](MctsPackingAlgorithm::policy) is also what
+/// [train_model](crate::nn::train_model) trains the network towards when given a number of
+/// simulations.
+///
+/// Each node the search expands counts as one attempt.
+pub struct MctsPackingAlgorithm<'a> {
+    pub model: &'a Model,
+    pub bounds: Rect,
+    /// The number of simulations to run before placing each rectangle.
+    pub simulations: usize,
+    /// How strongly the search favors positions with a high prior over positions with a high
+    /// value so far.
+    pub exploration: f32,
+    /// Whether rectangles may be rotated by 90 degrees. This must match how the model was trained.
+    pub allow_rotation: bool,
+    /// Regions of `bounds` that are already used, which rectangles are placed around.
+    pub obstacles: Vec<Rect>,
+    /// The multiple that the coordinates of each placement must be, with 1 allowing any position.
+    pub alignment: i32,
+}
+
+struct Node {
+    edges: Vec<Edge>,
+    visits: u32,
+}
+
+struct Edge {
+    /// The index of the position in the network's output.
+    action: usize,
+    prior: f32,
+    visits: u32,
+    value_sum: f32,
+    child: Option<usize>,
+}
+
+impl PackingAlgorithm for MctsPackingAlgorithm<'_> {
+    fn try_pack(&self, rects: &mut [Rect], _rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        let free_area = self.bounds.area() - self.obstacles.iter().map(Rect::area).sum::<i32>();
+        if rects.iter().map(Rect::area).sum::<i32>() > free_area {
+            return Err(PackingError::InfeasibleByArea);
+        }
+
+        let mut driver = self.model.driver();
+        let mut expansions = 0;
+
+        for i in 0..rects.len() {
+            let (packing, remaining) = rects.split_at(i);
+            let (policy, expanded) = self.search(&mut driver, packing, remaining);
+            expansions += expanded as u64;
+
+            let Some(action) = argmax(&policy, |i| policy[i] > 0.0) else {
+                return Err(PackingError::Unplaceable { item: i });
+            };
+
+            rects[i] = self.place(action, &rects[i]);
+        }
+
+        Ok(expansions)
+    }
+}
+
+impl MctsPackingAlgorithm<'_> {
+    /// Searches for the best position for `remaining[0]`, returning the fraction of simulations
+    /// that passed through each position in the same layout as the network's output.
+    ///
+    /// This is the improved policy that the network can be trained towards.
+    pub fn policy(
+        &self,
+        driver: &mut ModelDriver<impl Deref<Target = Model>>,
+        packing: &[Rect],
+        remaining: &[Rect],
+    ) -> Vec<f32> {
+        self.search(driver, packing, remaining).0
+    }
+
+    /// Computes the [policy](MctsPackingAlgorithm::policy) for `remaining[0]`, along with the
+    /// number of nodes the search expanded.
+    fn search(
+        &self,
+        driver: &mut ModelDriver<impl Deref<Target = Model>>,
+        packing: &[Rect],
+        remaining: &[Rect],
+    ) -> (Vec<f32>, usize) {
+        let mut policy = vec![0.0; position_count(&self.bounds, self.allow_rotation)];
+
+        let packing = &self
+            .obstacles
+            .iter()
+            .chain(packing)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut nodes = vec![self.expand(driver, packing, &remaining[0])];
+        if nodes[0].edges.is_empty() {
+            return (policy, nodes.len());
+        }
+
+        for _ in 0..self.simulations.max(1) {
+            self.simulate(driver, &mut nodes, packing, remaining);
+        }
+
+        let root = &nodes[0];
+        for edge in root.edges.iter() {
+            policy[edge.action] = edge.visits as f32 / root.visits as f32;
+        }
+
+        (policy, nodes.len())
+    }
+
+    /// Runs one simulation from the root, expanding a single new node and backing up its value.
+    fn simulate(
+        &self,
+        driver: &mut ModelDriver<impl Deref<Target = Model>>,
+        nodes: &mut Vec<Node>,
+        packing: &[Rect],
+        remaining: &[Rect],
+    ) {
+        let mut state = packing.to_vec();
+        let mut path = Vec::<(usize, usize)>::new();
+        let mut node = 0;
+
+        let value = loop {
+            let depth = state.len() - packing.len();
+
+            let Some(e) = self.select(&nodes[node]) else {
+                // no position is available for the next rectangle
+                break 0.0;
+            };
+
+            path.push((node, e));
+            state.push(self.place(nodes[node].edges[e].action, &remaining[depth]));
+
+            if let Some(child) = nodes[node].edges[e].child {
+                node = child;
+                continue;
+            }
+
+            let Some(next) = remaining.get(depth + 1) else {
+                break self.reward(&state);
+            };
+
+            let child = self.expand(driver, &state, next);
+            nodes.push(child);
+            let child = nodes.len() - 1;
+            nodes[node].edges[e].child = Some(child);
+
+            break self.rollout(driver, state, &remaining[depth + 1..]);
+        };
+
+        for (node, e) in path {
+            let node = &mut nodes[node];
+            node.visits += 1;
+            node.edges[e].visits += 1;
+            node.edges[e].value_sum += value;
+        }
+    }
+
+    /// Chooses the edge of `node` with the best upper confidence bound.
+    fn select(&self, node: &Node) -> Option<usize> {
+        // counting an unvisited node as visited once lets the priors decide its first descent
+        let sqrt_visits = (node.visits.max(1) as f32).sqrt();
+
+        node.edges
+            .iter()
+            .map(|edge| {
+                let q = if edge.visits > 0 {
+                    edge.value_sum / edge.visits as f32
+                } else {
+                    0.0
+                };
+                q + self.exploration * edge.prior * sqrt_visits / (1.0 + edge.visits as f32)
+            })
+            .enumerate()
+            .reduce(|a, b| if a.1 >= b.1 { a } else { b })
+            .map(|(e, _)| e)
+    }
+
+    /// Creates a node for placing `rect` into `packing`, with the network's output over the
+    /// valid positions as the priors of its edges.
+    fn expand(
+        &self,
+        driver: &mut ModelDriver<impl Deref<Target = Model>>,
+        packing: &[Rect],
+        rect: &Rect,
+    ) -> Node {
+        let input = vectorize_input(
+            &self.bounds,
+            packing,
+            rect.width(),
+            rect.height(),
+            self.allow_rotation,
+            self.alignment,
+        );
+        let result = driver.run(&input);
+        let output = result.output();
+
+        let valid = (0..output.len()).filter(|&i| input[i] == 1.0);
+
+        // softmax over the valid positions
+        let max = valid
+            .clone()
+            .map(|i| output[i])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let mut edges = valid
+            .map(|i| Edge {
+                action: i,
+                prior: (output[i] - max).exp(),
+                visits: 0,
+                value_sum: 0.0,
+                child: None,
+            })
+            .collect::<Vec<_>>();
+
+        let total = edges.iter().map(|e| e.prior).sum::<f32>();
+        edges.iter_mut().for_each(|e| e.prior /= total);
+
+        Node { edges, visits: 0 }
+    }
+
+    /// Places the `remaining` rectangles at the network's favorite valid positions, returning the
+    /// reward of the finished packing, or zero if some rectangle can't be placed.
+    fn rollout(
+        &self,
+        driver: &mut ModelDriver<impl Deref<Target = Model>>,
+        mut packing: Vec<Rect>,
+        remaining: &[Rect],
+    ) -> f32 {
+        for rect in remaining {
+            let input = vectorize_input(
+                &self.bounds,
+                &packing,
+                rect.width(),
+                rect.height(),
+                self.allow_rotation,
+                self.alignment,
+            );
+            let result = driver.run(&input);
+
+            let Some(action) = argmax(result.output(), |i| input[i] == 1.0) else {
+                return 0.0;
+            };
+
+            packing.push(self.place(action, rect));
+        }
+
+        self.reward(&packing)
+    }
+
+    /// Calculates the training reward of a finished packing that starts with the obstacles.
+    fn reward(&self, packing: &[Rect]) -> f32 {
+        reward(&self.bounds, &packing[self.obstacles.len()..])
+    }
+
+    /// Moves `rect` to the position with the given index in the network's output.
+    fn place(&self, action: usize, rect: &Rect) -> Rect {
+        devectorize_position(&self.bounds, action, rect.width(), rect.height())
+    }
+}
+
+/// Returns the index of the largest value in `values` among those allowed by `filter`.
+pub(crate) fn argmax(values: &[f32], filter: impl Fn(usize) -> bool) -> Option<usize> {
+    values
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| filter(i))
+        .reduce(|a, b| if a.1 >= b.1 { a } else { b })
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::algorithms::packing::network::NetworkPackingAlgorithm;
+
+    use super::*;
+
+    fn algorithm(model: &Model, simulations: usize) -> MctsPackingAlgorithm<'_> {
+        MctsPackingAlgorithm {
+            model,
+            bounds: Rect {
+                x1: 0,
+                y1: 0,
+                x2: 8,
+                y2: 8,
+            },
+            simulations,
+            exploration: 1.5,
+            allow_rotation: false,
+            obstacles: vec![],
+            alignment: 1,
+        }
+    }
+
+    #[test]
+    fn unvisited_node_follows_prior() {
+        let model = Model::new(&[66, 64]);
+        let node = Node {
+            edges: [0.1, 0.6, 0.3]
+                .into_iter()
+                .enumerate()
+                .map(|(action, prior)| Edge {
+                    action,
+                    prior,
+                    visits: 0,
+                    value_sum: 0.0,
+                    child: None,
+                })
+                .collect(),
+            visits: 0,
+        };
+
+        assert_eq!(algorithm(&model, 1).select(&node), Some(1));
+    }
+
+    #[test]
+    fn single_simulation_picks_prior_argmax() {
+        let model = Model::new(&[66, 64]);
+        let algorithm = algorithm(&model, 1);
+        let mut driver = model.driver();
+        let rects = [Rect {
+            x1: 0,
+            y1: 0,
+            x2: 3,
+            y2: 2,
+        }];
+
+        let root = algorithm.expand(&mut driver, &[], &rects[0]);
+        let best = root
+            .edges
+            .iter()
+            .reduce(|a, b| if a.prior >= b.prior { a } else { b })
+            .unwrap()
+            .action;
+
+        let policy = algorithm.policy(&mut driver, &[], &rects);
+        assert_eq!(policy[best], 1.0);
+    }
+
+    #[test]
+    fn counts_expanded_nodes() {
+        let model = Model::new(&[66, 64]);
+        let mut rects = [(3, 2), (2, 2), (1, 4)].map(|(w, h)| Rect {
+            x1: 0,
+            y1: 0,
+            x2: w,
+            y2: h,
+        });
+
+        // one simulation from each root expands one child, except from the last rectangle's
+        let attempts = algorithm(&model, 1)
+            .try_pack(&mut rects, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(attempts, 5);
+
+        // simulations that reach the end of an already expanded path expand nothing
+        let attempts = algorithm(&model, 100)
+            .try_pack(&mut rects, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert!(attempts < 3 * 101, "{attempts}");
+    }
+
+    #[test]
+    fn search_does_no_worse_than_the_network() {
+        let model = Model::new(&[66, 64]);
+        let mcts = algorithm(&model, 64);
+        let network = NetworkPackingAlgorithm {
+            model: &model,
+            bounds: mcts.bounds.clone(),
+            allow_rotation: false,
+            obstacles: vec![],
+            alignment: 1,
+        };
+
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let rects = (0..4)
+                .map(|_| Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: rng.gen_range(1..=4),
+                    y2: rng.gen_range(1..=4),
+                })
+                .collect::<Vec<_>>();
+
+            let [searched, greedy] = [&mcts as &dyn PackingAlgorithm, &network].map(|packer| {
+                let mut packing = rects.clone();
+                packer.try_pack(&mut packing, &mut rng).unwrap();
+                reward(&mcts.bounds, &packing)
+            });
+
+            assert!(searched >= greedy, "seed {seed}: {searched} < {greedy}");
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use neun::Model;
+use rand::RngCore;
+
+use crate::algorithms::online::network::OnlineNetworkPacker;
+use crate::geometry::Rect;
+
+use crate::{OnlinePacker, PackingAlgorithm, PackingError};
+
+/// Places each rectangle in turn at the network's favorite valid position within `bounds`, the
+/// same as when the network is used directly.
+///
+/// Rectangles are placed in the order given.
+pub struct NetworkPackingAlgorithm<'a> {
+    pub model: &'a Model,
+    pub bounds: Rect,
+    /// Whether rectangles may be rotated by 90 degrees. This must match how the model was trained.
+    pub allow_rotation: bool,
+    /// Regions of `bounds` that are already used, which rectangles are placed around.
+    pub obstacles: Vec<Rect>,
+    /// The multiple that the coordinates of each placement must be, with 1 allowing any position.
+    pub alignment: i32,
+}
+
+impl PackingAlgorithm for NetworkPackingAlgorithm<'_> {
+    fn try_pack(&self, rects: &mut [Rect], _rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        let mut packer = OnlineNetworkPacker {
+            driver: self.model.driver(),
+            bounds: self.bounds.clone(),
+            allow_rotation: self.allow_rotation,
+            alignment: self.alignment,
+            packing: self.obstacles.clone(),
+        };
+
+        for (i, rect) in rects.iter_mut().enumerate() {
+            let Some(placed) = packer.place(&rect.size()) else {
+                return Err(PackingError::Unplaceable { item: i });
+            };
+            *rect = placed;
+        }
+
+        Ok(rects.len() as u64)
+    }
+}
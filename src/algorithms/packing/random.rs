@@ -1,46 +1,100 @@
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, RngCore};
 
-use crate::geometry::Rect;
+use crate::geometry::{Rect, Size};
 
-use crate::PackingAlgorithm;
+use crate::{ObstaclePackingAlgorithm, PackingAlgorithm, PackingError};
 
-pub struct RandomPackingAlgorithm;
+pub struct RandomPackingAlgorithm {
+    /// The width of the strip to pack into. If `None`, rectangles are scattered over a region as
+    /// wide as all of them side by side.
+    pub width: Option<i32>,
+    /// Whether rectangles may be rotated by 90 degrees, which each one is with even chance.
+    pub allow_rotation: bool,
+}
 
 impl PackingAlgorithm for RandomPackingAlgorithm {
-    fn pack(&self, rects: &mut [Rect]) -> bool {
-        let mut rng = rand::thread_rng();
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.try_pack_around(rects, &[], rng)
+    }
+}
 
-        rects.shuffle(&mut rng);
+/// Positions that overlap an obstacle are rejected like those that overlap another rectangle.
+impl ObstaclePackingAlgorithm for RandomPackingAlgorithm {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        rects.shuffle(rng);
 
-        let outer_bounds = Rect {
-            x1: 0,
-            y1: 0,
-            x2: rects.iter().map(|r| r.x2 - r.x1).sum::<i32>(),
-            y2: rects.iter().map(|r| r.y2 - r.y1).sum::<i32>(),
-        };
+        let outer_bounds = outer_bounds(rects, obstacles, self.width, self.allow_rotation);
+        let mut attempts = 0;
 
         'outer: for i in 1..=rects.len() {
             let (rect, prev) = rects[..i].split_last_mut().unwrap();
+            let sizes = fitting_sizes(&outer_bounds, rect, self.allow_rotation);
+            if sizes.is_empty() {
+                return Err(PackingError::Unplaceable { item: i - 1 });
+            }
+
             for _ in 0..100 {
-                let width = rect.x2 - rect.x1;
-                let height = rect.y2 - rect.y1;
-                let x1 = rng.gen_range(outer_bounds.x1..outer_bounds.x2 - width);
-                let y1 = rng.gen_range(outer_bounds.y1..outer_bounds.y2 - height);
+                attempts += 1;
+                let size = sizes.choose(rng).unwrap();
+                let x1 = rng.gen_range(outer_bounds.x1..=outer_bounds.x2 - size.width);
+                let y1 = rng.gen_range(outer_bounds.y1..=outer_bounds.y2 - size.height);
                 let new_rect = Rect {
                     x1,
                     y1,
-                    x2: x1 + width,
-                    y2: y1 + height,
+                    x2: x1 + size.width,
+                    y2: y1 + size.height,
                 };
-                if prev.iter().all(|p| !new_rect.overlaps(p)) {
+                if prev.iter().chain(obstacles).all(|p| !new_rect.overlaps(p)) {
                     *rect = new_rect;
                     continue 'outer;
                 }
             }
-            return false;
+            return Err(PackingError::Unplaceable { item: i - 1 });
         }
 
-        true
+        Ok(attempts)
     }
 }
+
+/// Returns a region that the `rects` can certainly be packed into, with room to spare for random
+/// placement: as wide as their total width and as tall as their total height, or as long as their
+/// total longer side in both directions if they may be rotated.
+///
+/// If a strip `width` is given, the region is that wide instead. The region is made taller by the
+/// height of the `obstacles`, so that the rectangles still fit above all of them.
+pub(crate) fn outer_bounds(
+    rects: &[Rect],
+    obstacles: &[Rect],
+    width: Option<i32>,
+    allow_rotation: bool,
+) -> Rect {
+    let (x2, y2) = if allow_rotation {
+        let side = rects.iter().map(|r| r.width().max(r.height())).sum::<i32>();
+        (side, side)
+    } else {
+        (
+            rects.iter().map(|r| r.x2 - r.x1).sum::<i32>(),
+            rects.iter().map(|r| r.y2 - r.y1).sum::<i32>(),
+        )
+    };
+
+    Rect {
+        x1: 0,
+        y1: 0,
+        x2: width.unwrap_or(x2),
+        y2: y2 + obstacles.iter().map(|o| o.y2).max().unwrap_or(0).max(0),
+    }
+}
+
+/// Returns the orientations of `rect` that fit within `outer_bounds`.
+pub(crate) fn fitting_sizes(outer_bounds: &Rect, rect: &Rect, allow_rotation: bool) -> Vec<Size> {
+    rect.orientations(allow_rotation)
+        .filter(|s| s.width <= outer_bounds.width() && s.height <= outer_bounds.height())
+        .collect()
+}
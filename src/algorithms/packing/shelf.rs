@@ -0,0 +1,242 @@
+use rand::RngCore;
+
+use crate::geometry::{Rect, Size};
+
+use crate::{ObstaclePackingAlgorithm, PackingAlgorithm, PackingError, RectChoiceAlgorithm};
+
+use super::skyline::default_width;
+
+/// Places rectangles left to right along shelves, starting a new shelf past the last one whenever
+/// a rectangle doesn't fit on an existing shelf.
+///
+/// Rectangles are placed in the order given by `choice`. With a
+/// [MaxHeightRectChoiceAlgorithm](crate::algorithms::choosing::height::MaxHeightRectChoiceAlgorithm),
+/// the three [ShelfFit]s give the classic Next-Fit, First-Fit and Best-Fit Decreasing Height
+/// algorithms. Choosing by area instead, with a
+/// [MaxRectChoiceAlgorithm](crate::algorithms::choosing::minmax::MaxRectChoiceAlgorithm) or
+/// [MinRectChoiceAlgorithm](crate::algorithms::choosing::minmax::MinRectChoiceAlgorithm), places
+/// the largest or smallest rectangles first.
+pub struct ShelfPackingAlgorithm<C: RectChoiceAlgorithm> {
+    /// The width of the shelves. If `None`, a roughly square width is derived from the total area
+    /// of the rectangles.
+    pub width: Option<i32>,
+    pub choice: C,
+    pub fit: ShelfFit,
+    /// Whether rectangles may be rotated by 90 degrees. A rotatable rectangle is placed in the
+    /// tallest orientation that fits on an existing shelf, or the shortest one on a new shelf.
+    pub allow_rotation: bool,
+}
+
+/// Decides which shelf a rectangle is placed on.
+#[derive(Debug, Clone, Copy)]
+pub enum ShelfFit {
+    /// Only consider the most recently started shelf.
+    Next,
+    /// Use the first shelf that the rectangle fits on.
+    First,
+    /// Use the shelf that the rectangle leaves the least width remaining on.
+    Best,
+}
+
+/// The shelves of a partial packing, which rectangles can be placed on one at a time.
+#[derive(Debug, Clone)]
+pub struct Shelves {
+    width: i32,
+    shelves: Vec<Shelf>,
+    obstacles: Vec<Rect>,
+}
+
+#[derive(Debug, Clone)]
+struct Shelf {
+    y: i32,
+    height: i32,
+    /// Where the next rectangle on this shelf starts, past the rectangles and any obstacles
+    /// skipped so far.
+    used_width: i32,
+}
+
+impl<C: RectChoiceAlgorithm> PackingAlgorithm for ShelfPackingAlgorithm<C> {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.try_pack_around(rects, &[], rng)
+    }
+}
+
+/// Rectangles on a shelf skip past any obstacle in their way, and a new shelf starts above the
+/// obstacles if the rectangle starting it doesn't fit beside them.
+impl<C: RectChoiceAlgorithm> ObstaclePackingAlgorithm for ShelfPackingAlgorithm<C> {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let mut shelves = Shelves::new(
+            self.width
+                .unwrap_or_else(|| default_width(rects, self.allow_rotation)),
+        );
+        for obstacle in obstacles {
+            shelves.block(obstacle);
+        }
+
+        for i in 0..rects.len() {
+            let (packing, choices) = rects.split_at(i);
+            let chosen = i + self.choice.choose(packing, choices, rng);
+            rects.swap(i, chosen);
+
+            let Some(placed) = shelves.place(
+                rects[i].width(),
+                rects[i].height(),
+                self.fit,
+                self.allow_rotation,
+            ) else {
+                return Err(PackingError::Unplaceable { item: i });
+            };
+
+            rects[i] = placed;
+        }
+
+        Ok(rects.len() as u64)
+    }
+}
+
+impl Shelves {
+    /// Creates an empty set of shelves of the given `width`.
+    pub fn new(width: i32) -> Shelves {
+        Shelves {
+            width,
+            shelves: vec![],
+            obstacles: vec![],
+        }
+    }
+
+    /// Marks `obstacle` as used, so that later rectangles are placed around it.
+    pub fn block(&mut self, obstacle: &Rect) {
+        self.obstacles.push(obstacle.clone());
+    }
+
+    /// Returns the first `x` from `x` on that a rectangle of the given `size` can be placed at
+    /// with its bottom at `y` without overlapping an obstacle, or `None` if it would then stick
+    /// out past the shelves' width.
+    fn position(&self, mut x: i32, y: i32, size: &Size) -> Option<i32> {
+        loop {
+            if x + size.width > self.width {
+                return None;
+            }
+
+            let rect = Rect {
+                x1: x,
+                y1: y,
+                x2: x + size.width,
+                y2: y + size.height,
+            };
+            match self
+                .obstacles
+                .iter()
+                .filter(|o| o.overlaps(&rect))
+                .map(|o| o.x2)
+                .max()
+            {
+                Some(x2) => x = x2,
+                None => return Some(x),
+            }
+        }
+    }
+
+    /// Places a `width` by `height` rectangle on the shelf chosen by `fit`, starting a new shelf
+    /// if it doesn't fit on an existing one. Returns where it was placed, or `None` if it is wider
+    /// than the shelves.
+    ///
+    /// If `allow_rotation` is true, the rectangle is placed in the tallest orientation that fits
+    /// on an existing shelf, or the shortest one on a new shelf.
+    pub fn place(
+        &mut self,
+        width: i32,
+        height: i32,
+        fit: ShelfFit,
+        allow_rotation: bool,
+    ) -> Option<Rect> {
+        let shelf_width = self.width;
+
+        let unplaced = Rect {
+            x1: 0,
+            y1: 0,
+            x2: width,
+            y2: height,
+        };
+        let sizes = unplaced.orientations(allow_rotation).collect::<Vec<_>>();
+
+        // the tallest orientation that fits on the shelf, if any, and where it goes
+        let fits = |shelf: &Shelf| {
+            sizes
+                .iter()
+                .filter(|size| size.height <= shelf.height)
+                .filter_map(|size| Some((self.position(shelf.used_width, shelf.y, size)?, size)))
+                .max_by_key(|(_, size)| (size.height, -size.width))
+                .map(|(x, size)| (x, size.clone()))
+        };
+
+        let shelves = &self.shelves;
+        let found = match fit {
+            ShelfFit::Next => shelves
+                .len()
+                .checked_sub(1)
+                .and_then(|s| Some((s, fits(&shelves[s])?))),
+            ShelfFit::First => shelves
+                .iter()
+                .enumerate()
+                .find_map(|(s, shelf)| Some((s, fits(shelf)?))),
+            ShelfFit::Best => shelves
+                .iter()
+                .enumerate()
+                .filter_map(|(s, shelf)| Some((s, fits(shelf)?)))
+                .min_by_key(|(_, (x, size))| shelf_width - x - size.width),
+        };
+
+        let (shelf_index, (x, size)) = match found {
+            Some(found) => found,
+            None => {
+                // start a new shelf with the shortest orientation that fits
+                let size = sizes
+                    .iter()
+                    .filter(|size| size.width <= shelf_width)
+                    .min_by_key(|size| size.height)
+                    .cloned()?;
+
+                // move the shelf up past the obstacles until the rectangle fits beside them
+                let mut y = shelves.last().map_or(0, |shelf| shelf.y + shelf.height);
+                let x = loop {
+                    match self.position(0, y, &size) {
+                        Some(x) => break x,
+                        None => {
+                            y = self
+                                .obstacles
+                                .iter()
+                                .filter(|o| o.y1 < y + size.height && o.y2 > y)
+                                .map(|o| o.y2)
+                                .min()
+                                .unwrap()
+                        }
+                    }
+                };
+
+                self.shelves.push(Shelf {
+                    y,
+                    height: size.height,
+                    used_width: 0,
+                });
+                (self.shelves.len() - 1, (x, size))
+            }
+        };
+
+        let shelf = &mut self.shelves[shelf_index];
+        let rect = Rect {
+            x1: x,
+            y1: shelf.y,
+            x2: x + size.width,
+            y2: shelf.y + size.height,
+        };
+        shelf.used_width = x + size.width;
+
+        Some(rect)
+    }
+}
@@ -0,0 +1,384 @@
+use rand::RngCore;
+
+use crate::geometry::Rect;
+
+use crate::{ObstaclePackingAlgorithm, PackingAlgorithm, PackingError};
+
+/// Places each rectangle against a skyline, the profile formed by the `y2` edges of the rectangles
+/// placed so far.
+pub struct SkylinePackingAlgorithm {
+    /// The width of the skyline. If `None`, a roughly square width is derived from the total area
+    /// of the rectangles.
+    pub width: Option<i32>,
+    pub rule: SkylineRule,
+    /// Whether rectangles may be rotated by 90 degrees to fit better.
+    pub allow_rotation: bool,
+}
+
+/// Decides which skyline segment a rectangle is placed on.
+#[derive(Debug, Clone, Copy)]
+pub enum SkylineRule {
+    /// Minimize the `y2` edge of the placed rectangle, breaking ties by the leftmost position.
+    BottomLeft,
+    /// Minimize the area trapped behind the placed rectangle, breaking ties by its `y2` edge.
+    MinWaste,
+}
+
+/// The skyline of a partial packing, which rectangles can be placed against one at a time.
+#[derive(Debug, Clone)]
+pub struct Skyline {
+    width: i32,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    x: i32,
+    y: i32,
+    width: i32,
+}
+
+impl PackingAlgorithm for SkylinePackingAlgorithm {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.try_pack_around(rects, &[], rng)
+    }
+}
+
+/// Each obstacle raises the skyline to its `y2` edge, so the space below an obstacle is lost.
+impl ObstaclePackingAlgorithm for SkylinePackingAlgorithm {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        _rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let mut skyline = Skyline::new(
+            self.width
+                .unwrap_or_else(|| default_width(rects, self.allow_rotation)),
+        );
+        for obstacle in obstacles {
+            skyline.block(obstacle);
+        }
+
+        rects.sort_by(|a, b| {
+            b.height()
+                .cmp(&a.height())
+                .then_with(|| b.width().cmp(&a.width()))
+        });
+
+        for (i, rect) in rects.iter_mut().enumerate() {
+            let Some(placed) =
+                skyline.place(rect.width(), rect.height(), self.rule, self.allow_rotation)
+            else {
+                return Err(PackingError::Unplaceable { item: i });
+            };
+
+            *rect = placed;
+        }
+
+        Ok(rects.len() as u64)
+    }
+}
+
+impl Skyline {
+    /// Creates an empty skyline of the given `width`.
+    pub fn new(width: i32) -> Skyline {
+        Skyline {
+            width,
+            segments: vec![Segment { x: 0, y: 0, width }],
+        }
+    }
+
+    /// Returns the width of the skyline.
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// Raises the skyline to the `y2` edge of `obstacle` wherever it is lower, so that later
+    /// rectangles are placed above it.
+    pub fn block(&mut self, obstacle: &Rect) {
+        let (x1, x2) = (obstacle.x1.max(0), obstacle.x2.min(self.width));
+        if x1 >= x2 {
+            return;
+        }
+
+        let mut segments: Vec<Segment> = Vec::with_capacity(self.segments.len() + 2);
+        for segment in &self.segments {
+            let end = segment.x + segment.width;
+
+            // split the segment where the obstacle starts and ends
+            let cuts = [
+                segment.x,
+                x1.clamp(segment.x, end),
+                x2.clamp(segment.x, end),
+                end,
+            ];
+            for cut in cuts.windows(2).filter(|cut| cut[0] < cut[1]) {
+                let y = if cut[0] >= x1 && cut[1] <= x2 {
+                    segment.y.max(obstacle.y2)
+                } else {
+                    segment.y
+                };
+
+                match segments.last_mut() {
+                    Some(last) if last.y == y => last.width += cut[1] - cut[0],
+                    _ => segments.push(Segment {
+                        x: cut[0],
+                        y,
+                        width: cut[1] - cut[0],
+                    }),
+                }
+            }
+        }
+
+        self.segments = segments;
+    }
+
+    /// Places a `width` by `height` rectangle at the segment chosen by `rule`, returning where it
+    /// was placed, or `None` if it is wider than the skyline.
+    ///
+    /// If `allow_rotation` is true, the rectangle is also considered turned by 90 degrees, and is
+    /// placed in whichever orientation `rule` prefers.
+    pub fn place(
+        &mut self,
+        width: i32,
+        height: i32,
+        rule: SkylineRule,
+        allow_rotation: bool,
+    ) -> Option<Rect> {
+        let unplaced = Rect {
+            x1: 0,
+            y1: 0,
+            x2: width,
+            y2: height,
+        };
+
+        let (index, y, _, size) = unplaced
+            .orientations(allow_rotation)
+            .filter_map(|size| {
+                let (index, y, key) = self.find_position(size.width, size.height, rule)?;
+                Some((index, y, key, size))
+            })
+            .min_by_key(|&(_, _, key, _)| key)?;
+
+        let x = self.segments[index].x;
+        let rect = Rect {
+            x1: x,
+            y1: y,
+            x2: x + size.width,
+            y2: y + size.height,
+        };
+
+        self.add(index, &rect);
+
+        Some(rect)
+    }
+
+    /// Finds the index of the segment to place a `width` by `height` rectangle at, along with the
+    /// resulting y coordinate and the key `rule` ranks the position by.
+    fn find_position(
+        &self,
+        width: i32,
+        height: i32,
+        rule: SkylineRule,
+    ) -> Option<(usize, i32, (i32, i32))> {
+        (0..self.segments.len())
+            .filter_map(|i| {
+                let (y, waste) = self.fit(i, width)?;
+
+                let key = match rule {
+                    SkylineRule::BottomLeft => (y + height, self.segments[i].x),
+                    SkylineRule::MinWaste => (waste, y + height),
+                };
+
+                Some((i, y, key))
+            })
+            .min_by_key(|&(_, _, key)| key)
+    }
+
+    /// Calculates the y coordinate that a rectangle of the given `width` would rest at if its left
+    /// edge were aligned with segment `index`, along with the area wasted behind it.
+    ///
+    /// Returns `None` if the rectangle would extend past the right edge of the skyline.
+    fn fit(&self, index: usize, width: i32) -> Option<(i32, i32)> {
+        let x1 = self.segments[index].x;
+        let x2 = x1 + width;
+        if x2 > self.width {
+            return None;
+        }
+
+        let covered = self.segments[index..].iter().take_while(|s| s.x < x2);
+
+        // a zero-width rectangle covers no segments, so it rests on the one it's aligned with
+        let y = covered
+            .clone()
+            .map(|s| s.y)
+            .max()
+            .unwrap_or(self.segments[index].y);
+        let waste = covered
+            .map(|s| (y - s.y) * ((s.x + s.width).min(x2) - s.x))
+            .sum();
+
+        Some((y, waste))
+    }
+
+    /// Extends the skyline to account for `rect`, which was placed at segment `index`.
+    fn add(&mut self, index: usize, rect: &Rect) {
+        if rect.width() == 0 {
+            return;
+        }
+
+        let segments = &mut self.segments;
+
+        segments.insert(
+            index,
+            Segment {
+                x: rect.x1,
+                y: rect.y2,
+                width: rect.width(),
+            },
+        );
+
+        // shrink or remove the segments now hidden behind the new one
+        let i = index + 1;
+        while i < segments.len() && segments[i].x < rect.x2 {
+            let end = segments[i].x + segments[i].width;
+            if end <= rect.x2 {
+                segments.remove(i);
+            } else {
+                segments[i].width = end - rect.x2;
+                segments[i].x = rect.x2;
+                break;
+            }
+        }
+
+        // merge neighboring segments of equal height
+        let mut i = index.saturating_sub(1);
+        while i + 1 < segments.len() && i <= index + 1 {
+            if segments[i].y == segments[i + 1].y {
+                segments[i].width += segments[i + 1].width;
+                segments.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Chooses a skyline width that would give a square packing if no space were wasted, widened if
+/// needed so that every rectangle fits, in some orientation if `allow_rotation` is true.
+pub(crate) fn default_width(rects: &[Rect], allow_rotation: bool) -> i32 {
+    // the total area can overflow an i32 even when each rectangle's area doesn't
+    let area = rects
+        .iter()
+        .map(|r| r.width() as i64 * r.height() as i64)
+        .sum::<i64>();
+    let widest = rects
+        .iter()
+        .map(|r| {
+            if allow_rotation {
+                r.width().min(r.height())
+            } else {
+                r.width()
+            }
+        })
+        .max()
+        .unwrap_or(0);
+
+    ((area as f64).sqrt().ceil() as i32).max(widest)
+}
+
+/// Calculates the height of the rectangles stacked on top of each other, each standing on its
+/// longer side if `allow_rotation` is true. Any packing strategy fits within this height.
+pub(crate) fn stacked_height(rects: &[Rect], allow_rotation: bool) -> i32 {
+    rects
+        .iter()
+        .map(|r| {
+            if allow_rotation {
+                r.width().max(r.height())
+            } else {
+                r.height()
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn default_width_handles_large_areas() {
+        let rects = vec![
+            Rect {
+                x1: 0,
+                y1: 0,
+                x2: 40_000,
+                y2: 40_000,
+            };
+            4
+        ];
+
+        assert_eq!(default_width(&rects, false), 80_000);
+    }
+
+    #[test]
+    fn places_zero_sized_rects() {
+        let sizes = [(0, 3), (4, 0), (0, 0), (2, 5), (0, 7), (3, 3)];
+
+        for rule in [SkylineRule::BottomLeft, SkylineRule::MinWaste] {
+            for allow_rotation in [false, true] {
+                for width in [None, Some(5)] {
+                    let algorithm = SkylinePackingAlgorithm {
+                        width,
+                        rule,
+                        allow_rotation,
+                    };
+
+                    let input = sizes
+                        .iter()
+                        .map(|&(w, h)| Rect {
+                            x1: 0,
+                            y1: 0,
+                            x2: w,
+                            y2: h,
+                        })
+                        .collect::<Vec<_>>();
+                    let mut rects = input.clone();
+
+                    algorithm
+                        .pack(&mut rects, &mut StdRng::seed_from_u64(0))
+                        .unwrap();
+
+                    assert!(!Rect::match_items(&input, &rects).contains(&usize::MAX));
+                    assert!(rects.iter().all(|r| r.x1 >= 0 && r.y1 >= 0), "{rects:?}");
+
+                    // empty rectangles take up no space, so only the others can overlap
+                    let solid = rects.iter().filter(|r| !r.is_empty()).collect::<Vec<_>>();
+                    for (i, rect) in solid.iter().enumerate() {
+                        assert!(solid[..i].iter().all(|r| !r.overlaps(rect)), "{rects:?}");
+                    }
+                }
+            }
+        }
+
+        let empty = Rect {
+            x1: 0,
+            y1: 0,
+            x2: 0,
+            y2: 0,
+        };
+        let mut rects = vec![empty; 3];
+        let algorithm = SkylinePackingAlgorithm {
+            width: None,
+            rule: SkylineRule::BottomLeft,
+            allow_rotation: false,
+        };
+        assert!(algorithm
+            .pack(&mut rects, &mut StdRng::seed_from_u64(0))
+            .is_ok());
+    }
+}
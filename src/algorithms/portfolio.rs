@@ -0,0 +1,292 @@
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
+
+use crate::geometry::Rect;
+
+use crate::{PackingAlgorithm, PackingError, PackingHeuristic, PackingHeuristicScore};
+
+/// Runs each of the `members` in parallel and keeps the packing that `heuristic` scores best,
+/// preferring earlier members when scores are tied.
+///
+/// Members run on the current rayon pool, which is the global one unless this is called from
+/// within [rayon::ThreadPool::install]. Each run gets its own random number generator, seeded from
+/// the one given in a fixed order, so the result doesn't depend on how the runs are scheduled.
+pub struct PortfolioPackingAlgorithm<H: PackingHeuristic> {
+    pub members: Vec<PortfolioMember>,
+    pub heuristic: H,
+}
+
+/// One of the packers run by a [PortfolioPackingAlgorithm].
+pub struct PortfolioMember {
+    /// A name for the member, for reporting which members win.
+    pub name: String,
+    pub packer: Box<dyn PackingAlgorithm + Send + Sync>,
+    /// How many times to run the packer. Only randomized packers benefit from more than one run,
+    /// since each run then finds a different packing.
+    pub runs: usize,
+}
+
+/// The outcome of a [PortfolioPackingAlgorithm::pack_portfolio] call.
+#[derive(Clone, Debug)]
+pub struct PortfolioPacking<S> {
+    /// The index of the member whose packing was kept.
+    pub winner: usize,
+    /// The score of each member's best packing, or `None` for members that failed every run.
+    pub scores: Vec<Option<S>>,
+    /// The total number of attempts made over every run of every member.
+    pub attempts: u64,
+}
+
+/// How often each member of a portfolio won, over many calls.
+#[derive(Clone, Debug, Default)]
+pub struct PortfolioStats {
+    /// The number of wins of each member, by index.
+    pub wins: Vec<usize>,
+    /// The number of calls where every member failed.
+    pub failures: usize,
+}
+
+impl<H: PackingHeuristic + Sync> PackingAlgorithm for PortfolioPackingAlgorithm<H>
+where
+    H::Score: Send,
+{
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        Ok(self.pack_portfolio(rects, rng)?.attempts)
+    }
+}
+
+impl<H: PackingHeuristic + Sync> PortfolioPackingAlgorithm<H>
+where
+    H::Score: Send,
+{
+    /// Packs the `rects` like [PackingAlgorithm::pack], returning which member won and how each
+    /// of them scored if any of them found a packing.
+    ///
+    /// If every run fails, this returns the error of the first run, or
+    /// [PackingError::BudgetExhausted] if there were no runs at all.
+    pub fn pack_portfolio(
+        &self,
+        rects: &mut [Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<PortfolioPacking<H::Score>, PackingError> {
+        let runs = self
+            .members
+            .iter()
+            .enumerate()
+            .flat_map(|(i, member)| (0..member.runs).map(move |_| i))
+            .map(|i| (i, rng.gen::<u64>()))
+            .collect::<Vec<_>>();
+
+        let items = &*rects;
+        let results = runs
+            .into_par_iter()
+            .map(|(i, seed)| {
+                let mut packing = items.to_vec();
+                let mut rng = StdRng::seed_from_u64(seed);
+                (i, self.members[i].packer.pack(&mut packing, &mut rng))
+            })
+            .collect::<Vec<_>>();
+
+        // keep the best run of each member
+        let mut best = (0..self.members.len()).map(|_| None).collect::<Vec<_>>();
+        let mut attempts = 0;
+        let mut error = None;
+        for (i, result) in results {
+            let result = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    error.get_or_insert(err);
+                    continue;
+                }
+            };
+            attempts += result.attempts;
+
+            let packing = result.packing;
+            let score = self.heuristic.score(&packing);
+
+            let better = match &best[i] {
+                Some((best_score, _)) => score.is_better_than(best_score),
+                None => true,
+            };
+            if better {
+                best[i] = Some((score, packing));
+            }
+        }
+
+        let winner = (0..best.len())
+            .filter(|&i| best[i].is_some())
+            .reduce(|a, b| match (&best[a], &best[b]) {
+                (Some((a_score, _)), Some((b_score, _))) if b_score.is_better_than(a_score) => b,
+                _ => a,
+            })
+            .ok_or(error.unwrap_or(PackingError::BudgetExhausted))?;
+
+        let mut scores = Vec::with_capacity(best.len());
+        for (i, result) in best.into_iter().enumerate() {
+            scores.push(result.map(|(score, packing)| {
+                if i == winner {
+                    rects.clone_from_slice(&packing);
+                }
+                score
+            }));
+        }
+
+        Ok(PortfolioPacking {
+            winner,
+            scores,
+            attempts,
+        })
+    }
+}
+
+impl PortfolioStats {
+    /// Counts the outcome of a [PortfolioPackingAlgorithm::pack_portfolio] call.
+    pub fn record<S>(&mut self, result: Result<&PortfolioPacking<S>, &PackingError>) {
+        let Ok(result) = result else {
+            self.failures += 1;
+            return;
+        };
+
+        if self.wins.len() <= result.winner {
+            self.wins.resize(result.winner + 1, 0);
+        }
+        self.wins[result.winner] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::algorithms::packing::random::RandomPackingAlgorithm;
+    use crate::algorithms::packing::skyline::{SkylinePackingAlgorithm, SkylineRule};
+    use crate::heuristics::score::{score, ScorePackingHeuristic};
+
+    use super::*;
+
+    fn member(
+        name: &str,
+        packer: impl PackingAlgorithm + Send + Sync + 'static,
+    ) -> PortfolioMember {
+        PortfolioMember {
+            name: name.to_owned(),
+            packer: Box::new(packer),
+            runs: 3,
+        }
+    }
+
+    fn random() -> PortfolioMember {
+        member(
+            "random",
+            RandomPackingAlgorithm {
+                width: None,
+                allow_rotation: true,
+            },
+        )
+    }
+
+    fn skyline(width: Option<i32>) -> PortfolioMember {
+        member(
+            "skyline",
+            SkylinePackingAlgorithm {
+                width,
+                rule: SkylineRule::BottomLeft,
+                allow_rotation: false,
+            },
+        )
+    }
+
+    fn random_rects(rng: &mut StdRng) -> Vec<Rect> {
+        (0..10)
+            .map(|_| Rect {
+                x1: 0,
+                y1: 0,
+                x2: rng.gen_range(1..=8),
+                y2: rng.gen_range(1..=8),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_best_scoring_packing() {
+        let portfolio = PortfolioPackingAlgorithm {
+            members: vec![random(), skyline(Some(3)), skyline(None)],
+            heuristic: ScorePackingHeuristic,
+        };
+
+        for seed in 0..10 {
+            let mut rects = random_rects(&mut StdRng::seed_from_u64(seed));
+            let result = portfolio
+                .pack_portfolio(&mut rects, &mut StdRng::seed_from_u64(seed))
+                .unwrap();
+
+            // the narrow skyline can't fit anything wider than it
+            let fits_narrow = random_rects(&mut StdRng::seed_from_u64(seed))
+                .iter()
+                .all(|r| r.width() <= 3);
+            assert_eq!(result.scores[1].is_some(), fits_narrow, "seed {seed}");
+
+            let best = result.scores.iter().flatten().min().unwrap();
+            assert_eq!(result.scores[result.winner].as_ref(), Some(best));
+            assert_eq!(score(&rects), *best, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_member() {
+        let portfolio = PortfolioPackingAlgorithm {
+            members: vec![random(), skyline(None), skyline(None)],
+            heuristic: ScorePackingHeuristic,
+        };
+
+        for seed in 0..10 {
+            let mut rects = random_rects(&mut StdRng::seed_from_u64(seed));
+            let result = portfolio
+                .pack_portfolio(&mut rects, &mut StdRng::seed_from_u64(seed))
+                .unwrap();
+
+            // the two skylines always tie, so the second never wins
+            assert_eq!(result.scores[1], result.scores[2], "seed {seed}");
+            let expected = if result.scores[0] <= result.scores[1] {
+                0
+            } else {
+                1
+            };
+            assert_eq!(result.winner, expected, "seed {seed} {:?}", result.scores);
+        }
+    }
+
+    #[test]
+    fn records_wins_and_failures() {
+        let portfolio = PortfolioPackingAlgorithm {
+            members: vec![skyline(Some(3)), random(), skyline(None)],
+            heuristic: ScorePackingHeuristic,
+        };
+        let mut stats = PortfolioStats::default();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10 {
+            let mut rects = random_rects(&mut rng);
+            let result = portfolio.pack_portfolio(&mut rects, &mut rng);
+            stats.record(result.as_ref());
+        }
+
+        let wide = Rect {
+            x1: 0,
+            y1: 0,
+            x2: 5,
+            y2: 1,
+        };
+        let narrow = PortfolioPackingAlgorithm {
+            members: vec![skyline(Some(3))],
+            heuristic: ScorePackingHeuristic,
+        };
+        let result = narrow.pack_portfolio(&mut [wide], &mut rng);
+        assert!(matches!(result, Err(PackingError::Unplaceable { item: 0 })));
+        stats.record(result.as_ref());
+
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.wins.iter().sum::<usize>(), 10);
+        // the square skyline beats scattering the rectangles at random every time
+        assert_eq!(stats.wins.get(1).copied().unwrap_or(0), 0, "{stats:?}");
+    }
+}
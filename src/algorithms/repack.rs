@@ -0,0 +1,264 @@
+use crate::geometry::{Rect, Size, Spacing};
+
+use crate::{PackingHeuristic, PackingHeuristicScore};
+
+use super::packing::beam::corner_points;
+
+/// Updates an existing packing within `bounds` after items are added and removed, moving as few
+/// of the existing items as it can.
+///
+/// New items are placed around the existing ones first. If that doesn't fit, or if `stability`
+/// allows trading moves for a better score, the smallest existing items are taken out and put
+/// back afterwards, at their old positions where those are still free.
+pub struct RepackingAlgorithm<H: PackingHeuristic> {
+    pub bounds: Rect,
+    pub heuristic: H,
+    /// How much worse a packing's score may be, as measured by
+    /// [worse_by](PackingHeuristicScore::worse_by), in order to move one item fewer. Zero only
+    /// cares about the score, while a large value only cares about the number of items moved.
+    pub stability: f64,
+    /// Space to keep between items and from the border of `bounds`. Existing items are assumed to
+    /// keep it already.
+    pub spacing: Spacing,
+}
+
+/// The result of a [RepackingAlgorithm].
+#[derive(Debug, Clone)]
+pub struct Repacking {
+    /// The indices of the existing items that were not removed, in their original order.
+    pub kept: Vec<usize>,
+    /// The new positions of the `kept` items, followed by the positions of the added items.
+    pub placements: Vec<Rect>,
+    /// The indices of the existing items whose positions changed.
+    pub moved: Vec<usize>,
+}
+
+impl<H: PackingHeuristic> RepackingAlgorithm<H> {
+    /// Repacks the `existing` packing with the items at the indices in `removed` taken out and
+    /// items of the `added` sizes put in, returning `None` if no way to fit them was found.
+    pub fn repack(
+        &self,
+        existing: &[Rect],
+        removed: &[usize],
+        added: &[Size],
+    ) -> Option<Repacking> {
+        let kept = (0..existing.len())
+            .filter(|i| !removed.contains(i))
+            .collect::<Vec<_>>();
+
+        // the smallest items are the cheapest to take out and the easiest to put back
+        let mut eviction_order = (0..kept.len()).collect::<Vec<_>>();
+        eviction_order.sort_by_key(|&k| existing[kept[k]].area());
+
+        // try taking out none, one, two, four and so on, up to all of them
+        let mut evictions = std::iter::once(0)
+            .chain(std::iter::successors(Some(1usize), |&n| n.checked_mul(2)))
+            .take_while(|&n| n < kept.len())
+            .collect::<Vec<_>>();
+        evictions.push(kept.len());
+
+        let (candidates, scores): (Vec<_>, Vec<_>) = evictions
+            .into_iter()
+            .filter_map(|n| {
+                let candidate = self.try_repack(existing, &kept, &eviction_order[..n], added)?;
+                let score = self.heuristic.score(&candidate.0);
+                Some((candidate, score))
+            })
+            .unzip();
+
+        let best = &scores[H::Score::best(&scores)?];
+        let cost =
+            |i: usize| scores[i].worse_by(best) + self.stability * candidates[i].1.len() as f64;
+
+        // ties go to the candidate that took out the fewest items
+        let chosen = (0..candidates.len()).min_by(|&a, &b| cost(a).total_cmp(&cost(b)))?;
+        let (placements, moved) = candidates.into_iter().nth(chosen)?;
+
+        Some(Repacking {
+            moved: moved.into_iter().map(|k| kept[k]).collect(),
+            kept,
+            placements,
+        })
+    }
+
+    /// Places the `added` items around the `kept` items other than those at the `evicted`
+    /// positions in `kept`, then puts the evicted items back. Returns the placements of the kept
+    /// items followed by the added ones, along with the positions in `kept` of the items that
+    /// moved.
+    fn try_repack(
+        &self,
+        existing: &[Rect],
+        kept: &[usize],
+        evicted: &[usize],
+        added: &[Size],
+    ) -> Option<(Vec<Rect>, Vec<usize>)> {
+        let mut placements = kept
+            .iter()
+            .map(|&i| Some(existing[i].clone()))
+            .chain(added.iter().map(|_| None))
+            .collect::<Vec<_>>();
+        for &k in evicted {
+            placements[k] = None;
+        }
+
+        let mut order = (0..added.len())
+            .map(|a| (kept.len() + a, added[a].clone()))
+            .collect::<Vec<_>>();
+        order.sort_by_key(|(_, size)| -size.width * size.height);
+
+        let mut returning = evicted
+            .iter()
+            .map(|&k| (k, existing[kept[k]].size()))
+            .collect::<Vec<_>>();
+        returning.sort_by_key(|(_, size)| -size.width * size.height);
+        order.extend(returning);
+
+        let mut moved = vec![];
+
+        for (p, size) in order {
+            let packing = placements.iter().flatten().cloned().collect::<Vec<_>>();
+
+            // an evicted item stays where it was if nothing has taken its place
+            if p < kept.len() {
+                let old = &existing[kept[p]];
+                if packing
+                    .iter()
+                    .all(|r| !r.overlaps_spaced(old, self.spacing.gap))
+                {
+                    placements[p] = Some(old.clone());
+                    continue;
+                }
+                moved.push(p);
+            }
+
+            placements[p] = Some(self.best_position(&packing, &size)?);
+        }
+
+        moved.sort_unstable();
+
+        Some((placements.into_iter().flatten().collect(), moved))
+    }
+
+    /// Finds the corner point within `bounds` at which a rectangle of the given `size` gives
+    /// the best score when added to `packing`.
+    fn best_position(&self, packing: &[Rect], size: &Size) -> Option<Rect> {
+        let mut child = packing.to_vec();
+        let mut best: Option<(Rect, H::Score)> = None;
+
+        // corner points of the padded items are the closest positions that keep the spacing
+        let bounds = self.spacing.padded_bounds(&self.bounds);
+        let padded = packing
+            .iter()
+            .map(|r| self.spacing.padded(r, 0))
+            .collect::<Vec<_>>();
+
+        for (x, y) in corner_points(&padded, (bounds.x1, bounds.y1)) {
+            let placed = Rect {
+                x1: x,
+                y1: y,
+                x2: x + size.width,
+                y2: y + size.height,
+            };
+
+            if !bounds.contains(&self.spacing.padded(&placed, 0))
+                || packing
+                    .iter()
+                    .any(|p| p.overlaps_spaced(&placed, self.spacing.gap))
+            {
+                continue;
+            }
+
+            child.push(placed);
+            let score = self.heuristic.score(&child);
+            let placed = child.pop().unwrap();
+
+            if !matches!(&best, Some((_, best_score)) if !score.is_better_than(best_score)) {
+                best = Some((placed, score));
+            }
+        }
+
+        best.map(|(placed, _)| placed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::heuristics::score::ScorePackingHeuristic;
+
+    use super::*;
+
+    fn rect(x1: i32, y1: i32, x2: i32, y2: i32) -> Rect {
+        Rect { x1, y1, x2, y2 }
+    }
+
+    #[test]
+    fn only_moved_items_change_position() {
+        let algorithm = RepackingAlgorithm {
+            bounds: rect(0, 0, 12, 12),
+            heuristic: ScorePackingHeuristic,
+            stability: 0.0,
+            spacing: Spacing::default(),
+        };
+
+        let existing = [
+            rect(0, 0, 6, 6),
+            rect(6, 0, 9, 3),
+            rect(9, 0, 12, 2),
+            rect(0, 6, 4, 8),
+            rect(6, 3, 8, 5),
+            rect(4, 6, 5, 7),
+        ];
+        let removed = [2];
+        let kept = [0, 1, 3, 4, 5];
+        let added = [
+            Size {
+                width: 5,
+                height: 4,
+            },
+            Size {
+                width: 3,
+                height: 3,
+            },
+        ];
+
+        // evict the smallest items first, as `repack` does
+        let mut eviction_order = (0..kept.len()).collect::<Vec<_>>();
+        eviction_order.sort_by_key(|&k| existing[kept[k]].area());
+
+        for n in 0..=kept.len() {
+            let evicted = &eviction_order[..n];
+            let (placements, moved) = algorithm
+                .try_repack(&existing, &kept, evicted, &added)
+                .unwrap();
+
+            assert_eq!(placements.len(), kept.len() + added.len());
+            for (k, &i) in kept.iter().enumerate() {
+                let unchanged = placements[k] == existing[i];
+                assert_eq!(
+                    moved.contains(&k),
+                    !unchanged,
+                    "{n} evicted: {placements:?}"
+                );
+                assert!(
+                    unchanged || evicted.contains(&k),
+                    "{n} evicted: {placements:?}"
+                );
+            }
+
+            assert!(placements.iter().all(|r| algorithm.bounds.contains(r)));
+            for (i, r) in placements.iter().enumerate() {
+                assert!(
+                    placements[..i].iter().all(|q| !q.overlaps(r)),
+                    "{placements:?}"
+                );
+            }
+        }
+
+        let repacking = algorithm.repack(&existing, &removed, &added).unwrap();
+        assert_eq!(repacking.kept, kept);
+        for (k, &i) in repacking.kept.iter().enumerate() {
+            let unchanged = repacking.placements[k] == existing[i];
+            assert_eq!(repacking.moved.contains(&i), !unchanged, "{repacking:?}");
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use rand::RngCore;
+
+use crate::geometry::{Rect, Size, Spacing};
+
+use crate::{ObstaclePackingAlgorithm, OnlinePacker, PackingAlgorithm, PackingError};
+
+use super::obstacles::ObstructedPackingAlgorithm;
+
+/// Keeps the rectangles packed by `packer` apart by the given `spacing`, by packing rectangles
+/// grown by the spacing and shrinking them back afterwards.
+///
+/// The packer works with the grown rectangles as though the container's top left corner is at
+/// the origin, so a packer with a fixed width or bounds should be given the size of
+/// [Spacing::padded_bounds] of the container. Placements are moved by the margin afterwards.
+pub struct SpacedPackingAlgorithm<P: PackingAlgorithm> {
+    pub packer: P,
+    pub spacing: Spacing,
+}
+
+/// Keeps the rectangles placed by `packer` apart by the given `spacing`, the same way as
+/// [SpacedPackingAlgorithm].
+pub struct SpacedOnlinePacker<O: OnlinePacker> {
+    pub packer: O,
+    pub spacing: Spacing,
+}
+
+impl<P: PackingAlgorithm> PackingAlgorithm for SpacedPackingAlgorithm<P> {
+    fn try_pack(&self, rects: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        self.pack_padded(rects, &[], rng)
+    }
+}
+
+/// The obstacles are grown by the gap the same way as the rectangles, so that the rectangles keep
+/// the gap from them too.
+impl<P: ObstaclePackingAlgorithm> ObstaclePackingAlgorithm for SpacedPackingAlgorithm<P> {
+    fn try_pack_around(
+        &self,
+        rects: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let margin = self.spacing.margin;
+        let obstructed = SpacedPackingAlgorithm {
+            packer: ObstructedPackingAlgorithm {
+                packer: &self.packer,
+                obstacles: obstacles
+                    .iter()
+                    .map(|o| self.spacing.padded(o, 0).translated(-margin, -margin))
+                    .collect(),
+            },
+            spacing: self.spacing.clone(),
+        };
+
+        obstructed.pack_padded(rects, &[], rng)
+    }
+}
+
+impl<P: PackingAlgorithm> SpacedPackingAlgorithm<P> {
+    /// Packs the `rects` like [PackingAlgorithm::pack], additionally keeping each rectangle the
+    /// corresponding entry of `padding` away from everything else, or zero if there is none.
+    ///
+    /// The rectangles are packed with [PackingAlgorithm::pack_in_order], so they keep the order
+    /// given and each one's padding can be found again.
+    pub fn pack_padded(
+        &self,
+        rects: &mut [Rect],
+        padding: &[i32],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let padding = |i: usize| padding.get(i).copied().unwrap_or(0);
+
+        let mut placements = rects
+            .iter()
+            .enumerate()
+            .map(|(i, rect)| self.spacing.padded(rect, padding(i)))
+            .collect::<Vec<_>>();
+
+        let attempts = self.packer.pack_in_order(&mut placements, rng)?;
+
+        let margin = self.spacing.margin;
+        for (i, rect) in rects.iter_mut().enumerate() {
+            let unpadded = self.spacing.unpadded(&placements[i], padding(i));
+            *rect = unpadded.translated(margin, margin);
+        }
+
+        Ok(attempts)
+    }
+}
+
+impl<O: OnlinePacker> OnlinePacker for SpacedOnlinePacker<O> {
+    fn place(&mut self, size: &Size) -> Option<Rect> {
+        let padded = Size {
+            width: size.width + self.spacing.gap,
+            height: size.height + self.spacing.gap,
+        };
+
+        let placed = self.packer.place(&padded)?;
+        let unpadded = self.spacing.unpadded(&placed, 0);
+
+        let margin = self.spacing.margin;
+        Some(unpadded.translated(margin, margin))
+    }
+}
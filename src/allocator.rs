@@ -0,0 +1,363 @@
+use crate::geometry::{Rect, Size, Spacing};
+
+/// Hands out regions of `bounds` one at a time and takes them back, like a texture atlas.
+///
+/// Free space is tracked as a list of disjoint rectangles. Each allocation takes the free
+/// rectangle it fits most snugly, and splits the rest of it in two along the shorter leftover
+/// side. Freed regions are merged back with neighboring free space where possible.
+///
+/// Free space is kept in terms of regions grown by the [Spacing], so that regions handed out stay
+/// apart.
+#[derive(Debug, Clone)]
+pub struct AtlasAllocator {
+    bounds: Rect,
+    spacing: Spacing,
+    obstacles: Vec<Rect>,
+    free: Vec<Rect>,
+    allocations: Vec<Slot>,
+    /// Indices of `allocations` that are empty, to be reused.
+    vacant: Vec<usize>,
+}
+
+/// Identifies a region handed out by an [AtlasAllocator].
+///
+/// Ids of deallocated regions are never handed out again, so a stale id can't be mistaken for the
+/// region allocated in its place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AllocId {
+    index: usize,
+    generation: u32,
+}
+
+/// An entry of an allocator's list of allocations, which is reused once its region is deallocated.
+#[derive(Debug, Clone)]
+struct Slot {
+    /// How many times the entry has been deallocated.
+    generation: u32,
+    rect: Option<Rect>,
+}
+
+/// A region that was moved by [AtlasAllocator::defragment].
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub id: AllocId,
+    pub from: Rect,
+    pub to: Rect,
+}
+
+impl AtlasAllocator {
+    /// Creates an allocator with all of `bounds` free.
+    pub fn new(bounds: Rect) -> AtlasAllocator {
+        AtlasAllocator::with_spacing(bounds, Spacing::default())
+    }
+
+    /// Creates an allocator with all of `bounds` free, which keeps the regions it hands out apart
+    /// by the given `spacing`.
+    pub fn with_spacing(bounds: Rect, spacing: Spacing) -> AtlasAllocator {
+        AtlasAllocator {
+            free: vec![spacing.padded_bounds(&bounds)],
+            bounds,
+            spacing,
+            obstacles: vec![],
+            allocations: vec![],
+            vacant: vec![],
+        }
+    }
+
+    /// Allocates a region of the given `size`, returning `None` if no free space is big enough.
+    pub fn allocate(&mut self, size: &Size) -> Option<AllocId> {
+        let size = self.padded_size(size);
+        let padded = place(&mut self.free, &size)?;
+        let rect = self.spacing.unpadded(&padded, 0);
+
+        let index = match self.vacant.pop() {
+            Some(index) => {
+                self.allocations[index].rect = Some(rect);
+                index
+            }
+            None => {
+                self.allocations.push(Slot {
+                    generation: 0,
+                    rect: Some(rect),
+                });
+                self.allocations.len() - 1
+            }
+        };
+
+        Some(AllocId {
+            index,
+            generation: self.allocations[index].generation,
+        })
+    }
+
+    /// Frees the region allocated as `id`, merging it with any free space it lines up with.
+    ///
+    /// Returns false and changes nothing if `id` has already been deallocated, even if its place
+    /// has since been reused by another region.
+    pub fn deallocate(&mut self, id: AllocId) -> bool {
+        let Some(slot) = self.allocations.get_mut(id.index) else {
+            return false;
+        };
+        if slot.generation != id.generation {
+            return false;
+        }
+        let Some(rect) = slot.rect.take() else {
+            return false;
+        };
+        slot.generation += 1;
+        self.vacant.push(id.index);
+
+        // space under an obstacle stays used even once the region over it is freed
+        let mut pieces = vec![self.spacing.padded(&rect, 0)];
+        for obstacle in &self.obstacles {
+            cut_out(&mut pieces, &self.spacing.padded(obstacle, 0));
+        }
+
+        for mut rect in pieces.into_iter().filter(|r| !r.is_empty()) {
+            // keep merging until nothing lines up with the grown rectangle
+            while let Some((i, merged)) = self
+                .free
+                .iter()
+                .enumerate()
+                .find_map(|(i, free)| Some((i, rect.merge(free)?)))
+            {
+                self.free.swap_remove(i);
+                rect = merged;
+            }
+
+            self.free.push(rect);
+        }
+
+        true
+    }
+
+    /// Marks `obstacle` as permanently used, so that no region is handed out overlapping it.
+    /// Regions already allocated there are left where they are, and only the space around the
+    /// obstacle is freed when they are deallocated.
+    pub fn block(&mut self, obstacle: Rect) {
+        cut_out(&mut self.free, &self.spacing.padded(&obstacle, 0));
+        self.obstacles.push(obstacle);
+    }
+
+    /// Returns the region allocated as `id`, or `None` if it has been deallocated.
+    pub fn get(&self, id: AllocId) -> Option<&Rect> {
+        let slot = self.allocations.get(id.index)?;
+        (slot.generation == id.generation)
+            .then_some(slot.rect.as_ref())
+            .flatten()
+    }
+
+    /// Returns the free space, as disjoint rectangles grown by the spacing.
+    pub fn free_rects(&self) -> &[Rect] {
+        &self.free
+    }
+
+    /// Merges all of the free space that lines up into larger rectangles. Deallocation only merges
+    /// the freed region, so free space split up by earlier allocations may need this to be reused
+    /// for larger ones.
+    pub fn coalesce(&mut self) {
+        Rect::simplify(&mut self.free);
+    }
+
+    /// Packs every allocated region again from scratch, tallest first, so that the free space is
+    /// left in as few pieces as possible. Returns the regions that moved, which must be copied to
+    /// their new places.
+    ///
+    /// Returns `None` and moves nothing if the regions don't all fit when packed again.
+    pub fn defragment(&mut self) -> Option<Vec<Move>> {
+        let mut order = (0..self.allocations.len())
+            .filter(|&i| self.allocations[i].rect.is_some())
+            .collect::<Vec<_>>();
+        order.sort_by_key(|&i| {
+            let rect = self.allocations[i].rect.as_ref().unwrap();
+            (-rect.height(), -rect.width())
+        });
+
+        let mut free = vec![self.spacing.padded_bounds(&self.bounds)];
+        for obstacle in &self.obstacles {
+            cut_out(&mut free, &self.spacing.padded(obstacle, 0));
+        }
+        let mut placed = Vec::with_capacity(order.len());
+
+        for &i in &order {
+            let size = self.padded_size(&self.allocations[i].rect.as_ref().unwrap().size());
+            let padded = place(&mut free, &size)?;
+            placed.push(self.spacing.unpadded(&padded, 0));
+        }
+
+        self.free = free;
+
+        let moves = order
+            .into_iter()
+            .zip(placed)
+            .filter_map(|(i, to)| {
+                let slot = &mut self.allocations[i];
+                let from = slot.rect.replace(to.clone()).unwrap();
+                (from != to).then_some(Move {
+                    id: AllocId {
+                        index: i,
+                        generation: slot.generation,
+                    },
+                    from,
+                    to,
+                })
+            })
+            .collect();
+
+        Some(moves)
+    }
+
+    /// Returns `size` grown by the gap kept to the right of and below each region.
+    fn padded_size(&self, size: &Size) -> Size {
+        Size {
+            width: size.width + self.spacing.gap,
+            height: size.height + self.spacing.gap,
+        }
+    }
+}
+
+/// Places a rectangle of the given `size` in the free rectangle that leaves the shortest side
+/// over, splitting what remains of it along the shorter leftover side.
+fn place(free: &mut Vec<Rect>, size: &Size) -> Option<Rect> {
+    let index = free
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.width() >= size.width && f.height() >= size.height)
+        .min_by_key(|(_, f)| {
+            let leftover_horz = f.width() - size.width;
+            let leftover_vert = f.height() - size.height;
+            (leftover_horz.min(leftover_vert), f.area())
+        })?
+        .0;
+
+    let f = free.swap_remove(index);
+    let rect = Rect {
+        x1: f.x1,
+        y1: f.y1,
+        x2: f.x1 + size.width,
+        y2: f.y1 + size.height,
+    };
+
+    let (right, below) = if f.width() - size.width < f.height() - size.height {
+        // the leftover strip to the right is thinner, so keep it short
+        (
+            Rect {
+                x1: rect.x2,
+                y2: rect.y2,
+                ..f.clone()
+            },
+            Rect {
+                y1: rect.y2,
+                ..f.clone()
+            },
+        )
+    } else {
+        (
+            Rect {
+                x1: rect.x2,
+                ..f.clone()
+            },
+            Rect {
+                y1: rect.y2,
+                x2: rect.x2,
+                ..f.clone()
+            },
+        )
+    };
+
+    free.extend([right, below].into_iter().filter(|r| !r.is_empty()));
+
+    Some(rect)
+}
+
+/// Removes `rect` from the free space, splitting any free rectangle it overlaps.
+fn cut_out(free: &mut Vec<Rect>, rect: &Rect) {
+    let mut i = 0;
+    while i < free.len() {
+        if free[i].overlaps(rect) {
+            let free_rect = free.swap_remove(i);
+            free.extend(free_rect.cut_out(rect));
+        } else {
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(width: i32, height: i32) -> Size {
+        Size { width, height }
+    }
+
+    fn bounds() -> Rect {
+        Rect {
+            x1: 0,
+            y1: 0,
+            x2: 16,
+            y2: 16,
+        }
+    }
+
+    #[test]
+    fn stale_id_is_rejected() {
+        let mut allocator = AtlasAllocator::new(bounds());
+
+        let x = allocator.allocate(&size(4, 4)).unwrap();
+        assert!(allocator.deallocate(x));
+        let y = allocator.allocate(&size(4, 4)).unwrap();
+
+        assert_ne!(x, y);
+        assert_eq!(allocator.get(x), None);
+        assert!(allocator.get(y).is_some());
+
+        let free = allocator.free_rects().to_vec();
+        assert!(!allocator.deallocate(x));
+        assert_eq!(allocator.free_rects(), free);
+        assert!(allocator.get(y).is_some());
+    }
+
+    #[test]
+    fn defragment_reports_whether_regions_fit() {
+        let mut allocator = AtlasAllocator::new(bounds());
+        let a = allocator.allocate(&size(8, 8)).unwrap();
+        allocator.allocate(&size(8, 8)).unwrap();
+        allocator.deallocate(a);
+
+        assert_eq!(allocator.defragment().map(|moves| moves.len()), Some(1));
+        assert_eq!(allocator.defragment().map(|moves| moves.len()), Some(0));
+
+        // a region allocated before an obstacle was placed over the only spot it fits
+        let mut allocator = AtlasAllocator::new(bounds());
+        let a = allocator.allocate(&size(16, 16)).unwrap();
+        allocator.block(Rect {
+            x1: 0,
+            y1: 0,
+            x2: 1,
+            y2: 1,
+        });
+
+        assert!(allocator.defragment().is_none());
+        assert_eq!(allocator.get(a), Some(&bounds()));
+    }
+
+    #[test]
+    fn freeing_a_blocked_region_keeps_the_obstacle() {
+        let mut allocator = AtlasAllocator::new(bounds());
+        let a = allocator.allocate(&size(16, 16)).unwrap();
+        let obstacle = Rect {
+            x1: 4,
+            y1: 4,
+            x2: 12,
+            y2: 12,
+        };
+        allocator.block(obstacle.clone());
+        assert!(allocator.deallocate(a));
+
+        assert!(allocator.allocate(&size(16, 16)).is_none());
+        while let Some(b) = allocator.allocate(&size(4, 4)) {
+            assert!(!allocator.get(b).unwrap().overlaps(&obstacle));
+        }
+    }
+}
@@ -6,6 +6,76 @@ pub struct Size {
     pub height: i32,
 }
 
+impl Size {
+    /// Returns this size turned by 90 degrees, with its width and height swapped.
+    pub const fn rotated(&self) -> Size {
+        Size {
+            width: self.height,
+            height: self.width,
+        }
+    }
+}
+
+/// Space to leave between rectangles, and between rectangles and the border of their container.
+#[derive(Debug, Clone, Default)]
+pub struct Spacing {
+    /// The minimum distance between any two rectangles.
+    pub gap: i32,
+    /// The minimum distance between any rectangle and the border of the container.
+    pub margin: i32,
+}
+
+impl Spacing {
+    /// Grows `rect` by `padding` on every side, plus the gap on its right and bottom sides, so
+    /// that padded rectangles which don't overlap are far enough apart.
+    pub const fn padded(&self, rect: &Rect, padding: i32) -> Rect {
+        Rect {
+            x1: rect.x1 - padding,
+            y1: rect.y1 - padding,
+            x2: rect.x2 + padding + self.gap,
+            y2: rect.y2 + padding + self.gap,
+        }
+    }
+
+    /// Undoes [Spacing::padded], shrinking a padded rectangle back to the rectangle itself.
+    pub const fn unpadded(&self, rect: &Rect, padding: i32) -> Rect {
+        Rect {
+            x1: rect.x1 + padding,
+            y1: rect.y1 + padding,
+            x2: rect.x2 - padding - self.gap,
+            y2: rect.y2 - padding - self.gap,
+        }
+    }
+
+    /// Returns the region of `bounds` that [padded](Spacing::padded) rectangles must lie within:
+    /// `bounds` shrunk by the margin, then grown by the gap on its right and bottom sides to make
+    /// room for the gap included with each rectangle.
+    pub const fn padded_bounds(&self, bounds: &Rect) -> Rect {
+        Rect {
+            x1: bounds.x1 + self.margin,
+            y1: bounds.y1 + self.margin,
+            x2: bounds.x2 - self.margin + self.gap,
+            y2: bounds.y2 - self.margin + self.gap,
+        }
+    }
+
+    /// Returns true if the rectangles in `packing` lie within `bounds` and keep this spacing from
+    /// each other and from the border, where each rectangle also keeps the corresponding entry of
+    /// `padding`, or zero if there is none.
+    pub fn is_valid(&self, bounds: &Rect, packing: &[Rect], padding: &[i32]) -> bool {
+        let bounds = self.padded_bounds(bounds);
+        let padded = packing
+            .iter()
+            .enumerate()
+            .map(|(i, rect)| self.padded(rect, padding.get(i).copied().unwrap_or(0)))
+            .collect::<Vec<_>>();
+
+        padded.iter().enumerate().all(|(i, rect)| {
+            bounds.contains(rect) && padded[..i].iter().all(|other| !rect.overlaps(other))
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Interval {
     pub start: i32,
@@ -25,6 +95,11 @@ impl Interval {
         self.end - self.start
     }
 
+    /// Returns true if this interval has no length.
+    pub const fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
     /// Returns true if this interval overlaps with `other`.
     pub const fn overlaps(&self, other: &Interval) -> bool {
         self.start < other.end && self.end > other.start
@@ -56,7 +131,7 @@ pub enum Side {
     Bottom,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Rect {
     pub x1: i32,
     pub y1: i32,
@@ -106,6 +181,91 @@ impl Rect {
         }
     }
 
+    /// Returns this rectangle turned by 90 degrees about its `x1`, `y1` corner, with its width and
+    /// height swapped.
+    pub const fn rotated(&self) -> Rect {
+        Rect {
+            x1: self.x1,
+            y1: self.y1,
+            x2: self.x1 + self.height(),
+            y2: self.y1 + self.width(),
+        }
+    }
+
+    /// Returns this rectangle moved right by `dx` and down by `dy`.
+    pub const fn translated(&self, dx: i32, dy: i32) -> Rect {
+        Rect {
+            x1: self.x1 + dx,
+            y1: self.y1 + dy,
+            x2: self.x2 + dx,
+            y2: self.y2 + dy,
+        }
+    }
+
+    /// Returns the sizes this rectangle can be placed with: its own size, followed by its rotated
+    /// size if `allow_rotation` is true and the rectangle isn't square.
+    pub fn orientations(&self, allow_rotation: bool) -> impl Iterator<Item = Size> {
+        let size = self.size();
+        let rotated = (allow_rotation && size.width != size.height).then(|| size.rotated());
+
+        std::iter::once(size).chain(rotated)
+    }
+
+    /// Finds the index of the item that each rectangle of `packing` was packed from, where the
+    /// `items` must be the same rectangles in any order and orientation.
+    ///
+    /// Rectangles are matched to items of the same size where possible, and only then to items of
+    /// the rotated size. Rectangles that match no remaining item are given `usize::MAX`.
+    pub fn match_items(items: &[Rect], packing: &[Rect]) -> Vec<usize> {
+        let mut matched = vec![false; items.len()];
+        let mut matches = vec![usize::MAX; packing.len()];
+
+        for rotated in [false, true] {
+            for (rect, m) in packing.iter().zip(matches.iter_mut()) {
+                if *m != usize::MAX {
+                    continue;
+                }
+
+                let size = if rotated {
+                    rect.size().rotated()
+                } else {
+                    rect.size()
+                };
+
+                let found = (0..items.len()).find(|&i| {
+                    !matched[i]
+                        && items[i].width() == size.width
+                        && items[i].height() == size.height
+                });
+
+                if let Some(i) = found {
+                    matched[i] = true;
+                    *m = i;
+                }
+            }
+        }
+
+        matches
+    }
+
+    /// Determines which rectangles of `packing` were rotated relative to the `items` they were
+    /// packed from, which must be the same rectangles in any order and orientation.
+    ///
+    /// Rectangles are matched to items as by [Rect::match_items], so an item is only reported as
+    /// rotated if no unrotated item accounts for it. Rectangles that match no item aren't reported
+    /// as rotated.
+    pub fn rotations(items: &[Rect], packing: &[Rect]) -> Vec<bool> {
+        Rect::match_items(items, packing)
+            .into_iter()
+            .zip(packing.iter())
+            .map(|(i, rect)| {
+                items
+                    .get(i)
+                    .is_some_and(|item| item.width() != rect.width())
+            })
+            .collect()
+    }
+
     /// Returns the horizontal range of this rectangle as an [Interval].
     pub const fn horz(&self) -> Interval {
         Interval {
@@ -137,6 +297,15 @@ impl Rect {
         self.horz().overlaps(&other.horz()) && self.vert().overlaps(&other.vert())
     }
 
+    /// Returns true if `self` is closer than `gap` to `other` along both axes. With a `gap` of
+    /// zero, this is the same as [Rect::overlaps].
+    pub const fn overlaps_spaced(&self, other: &Rect, gap: i32) -> bool {
+        self.x1 < other.x2 + gap
+            && other.x1 < self.x2 + gap
+            && self.y1 < other.y2 + gap
+            && other.y1 < self.y2 + gap
+    }
+
     /// Returns true if `self` fully contains `other`.
     pub const fn contains(&self, other: &Rect) -> bool {
         self.horz().contains(&other.horz()) && self.vert().contains(&other.vert())
@@ -225,6 +394,36 @@ impl Rect {
             .filter(|r| !r.is_empty())
     }
 
+    /// Cuts `other` out of `self`, returning an iterator of up to four maximal rectangles that
+    /// together cover what remains.
+    ///
+    /// Unlike [Rect::cut_out], the resulting rectangles may overlap each other.
+    pub fn cut_out_maximal(&self, other: &Rect) -> impl Iterator<Item = Rect> {
+        let left = Rect {
+            x2: other.x1.min(self.x2),
+            ..self.clone()
+        };
+
+        let top = Rect {
+            y2: other.y1.min(self.y2),
+            ..self.clone()
+        };
+
+        let right = Rect {
+            x1: other.x2.max(self.x1),
+            ..self.clone()
+        };
+
+        let bottom = Rect {
+            y1: other.y2.max(self.y1),
+            ..self.clone()
+        };
+
+        [left, top, right, bottom]
+            .into_iter()
+            .filter(|r| r.x1 < r.x2 && r.y1 < r.y2)
+    }
+
     pub fn inverse(packing: &[Rect]) -> Vec<Rect> {
         let mut inverted = vec![Rect::bbox(packing.iter())];
         for rect in packing {
@@ -263,10 +462,10 @@ impl Rect {
                 if let Some(merged) = rects[i].merge(&rects[j]) {
                     rects[i] = merged;
                     rects.remove(j);
-                    j = 0;
                     if j < i {
                         i -= 1;
                     }
+                    j = 0;
                 } else {
                     j += 1;
                 }
@@ -275,6 +474,34 @@ impl Rect {
             i += 1;
         }
     }
+
+    /// Returns true if the given rectangles, which must not overlap, can be separated from each
+    /// other by a sequence of edge-to-edge cuts.
+    pub fn is_guillotine(rects: &[Rect]) -> bool {
+        if rects.len() <= 1 {
+            return true;
+        }
+
+        let bbox = Rect::bbox(rects.iter());
+
+        for cut in rects.iter().map(|r| r.x2).filter(|&x| x < bbox.x2) {
+            if rects.iter().all(|r| r.x2 <= cut || r.x1 >= cut) {
+                let (left, right): (Vec<Rect>, Vec<Rect>) =
+                    rects.iter().cloned().partition(|r| r.x2 <= cut);
+                return Self::is_guillotine(&left) && Self::is_guillotine(&right);
+            }
+        }
+
+        for cut in rects.iter().map(|r| r.y2).filter(|&y| y < bbox.y2) {
+            if rects.iter().all(|r| r.y2 <= cut || r.y1 >= cut) {
+                let (top, bottom): (Vec<Rect>, Vec<Rect>) =
+                    rects.iter().cloned().partition(|r| r.y2 <= cut);
+                return Self::is_guillotine(&top) && Self::is_guillotine(&bottom);
+            }
+        }
+
+        false
+    }
 }
 
 impl Serialize for Size {
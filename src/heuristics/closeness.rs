@@ -40,6 +40,10 @@ impl PackingHeuristicScore<ClosenessPackingHeuristic> for f32 {
         self > other
     }
 
+    fn worse_by(&self, other: &Self) -> f64 {
+        (other - self) as f64
+    }
+
     fn best(scores: &[Self]) -> Option<usize> {
         scores
             .iter()
@@ -0,0 +1,85 @@
+use crate::geometry::Rect;
+
+use crate::{PackingHeuristic, PackingHeuristicScore};
+
+/// Calculates the height of strip used by a packing, measured from zero to the furthest `y2` edge.
+pub fn height(packing: &[Rect]) -> i32 {
+    packing.iter().map(|r| r.y2).max().unwrap_or(0).max(0)
+}
+
+/// Calculates a lower bound on the [height] of any packing of `rects` into a strip of the given
+/// `width`: the strip must hold the rectangles' total area, and must be at least as tall as each
+/// rectangle in its shortest orientation that fits within `width`.
+///
+/// Returns `None` if `width` isn't positive or some rectangle doesn't fit within it at all.
+pub fn height_lower_bound(rects: &[Rect], width: i32, allow_rotation: bool) -> Option<i32> {
+    if width <= 0 {
+        return None;
+    }
+
+    let area = rects
+        .iter()
+        .map(|r| r.width() as i64 * r.height() as i64)
+        .sum::<i64>();
+    let area_bound = ((area + width as i64 - 1) / width as i64) as i32;
+
+    let tallest = rects
+        .iter()
+        .map(|r| {
+            r.orientations(allow_rotation)
+                .filter(|s| s.width <= width)
+                .map(|s| s.height)
+                .min()
+        })
+        .try_fold(0, |acc, h| Some(acc.max(h?)))?;
+
+    Some(area_bound.max(tallest))
+}
+
+pub struct HeightPackingHeuristic;
+
+impl PackingHeuristic for HeightPackingHeuristic {
+    type Score = i32;
+
+    fn score(&self, packing: &[Rect]) -> i32 {
+        height(packing)
+    }
+}
+
+impl PackingHeuristicScore<HeightPackingHeuristic> for i32 {
+    fn is_better_than(&self, other: &Self) -> bool {
+        self < other
+    }
+
+    fn worse_by(&self, other: &Self) -> f64 {
+        (self - other) as f64
+    }
+
+    fn best(scores: &[Self]) -> Option<usize> {
+        scores
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.cmp(b.1))
+            .map(|(i, _)| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lower_bound_needs_a_positive_width() {
+        let rects = [Rect {
+            x1: 0,
+            y1: 0,
+            x2: 1,
+            y2: 3,
+        }];
+
+        assert_eq!(height_lower_bound(&rects, 0, true), None);
+        assert_eq!(height_lower_bound(&rects, -1, true), None);
+        assert_eq!(height_lower_bound(&rects, 1, true), Some(3));
+        assert_eq!(height_lower_bound(&[], 0, false), None);
+    }
+}
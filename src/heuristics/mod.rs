@@ -1,3 +1,4 @@
 pub mod closeness;
+pub mod height;
 pub mod score;
 pub mod spread;
@@ -2,7 +2,13 @@ use crate::geometry::Rect;
 
 use crate::{PackingHeuristic, PackingHeuristicScore};
 
+/// Returns the area of the bounding box of `packing` that isn't covered by its rectangles, which
+/// is zero for an empty packing.
 pub fn score(packing: &[Rect]) -> i32 {
+    if packing.is_empty() {
+        return 0;
+    }
+
     let mut bounds = Rect {
         x1: i32::MAX,
         y1: i32::MAX,
@@ -37,6 +43,10 @@ impl PackingHeuristicScore<ScorePackingHeuristic> for i32 {
         self < other
     }
 
+    fn worse_by(&self, other: &Self) -> f64 {
+        (self - other) as f64
+    }
+
     fn best(scores: &[Self]) -> Option<usize> {
         scores
             .iter()
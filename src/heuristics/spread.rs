@@ -33,6 +33,10 @@ impl PackingHeuristicScore<SpreadPackingHeuristic> for f64 {
         self < other
     }
 
+    fn worse_by(&self, other: &Self) -> f64 {
+        self - other
+    }
+
     fn best(scores: &[Self]) -> Option<usize> {
         scores
             .iter()
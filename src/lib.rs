@@ -0,0 +1,11 @@
+pub mod algorithms;
+pub mod allocator;
+pub mod geometry;
+pub mod heuristics;
+pub mod nn;
+mod result;
+pub mod sequence_pair;
+mod traits;
+
+pub use result::*;
+pub use traits::*;
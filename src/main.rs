@@ -4,27 +4,30 @@ use std::{
     path::PathBuf,
 };
 
-use base64::prelude::*;
 use clap::{Parser, Subcommand};
 use neun::Model;
 use rand::prelude::*;
 
-mod algorithms;
-mod geometry;
-mod heuristics;
-mod nn;
-mod traits;
-
-use nn::{evaluate_model, load_weights, store_weights, train_model, TrainingParameters};
-pub use traits::*;
+use reipack::algorithms::bins::identical::{BinPackingAlgorithm, BinSelection};
+use reipack::algorithms::knapsack::KnapsackPackingAlgorithm;
+use reipack::algorithms::packing::network::NetworkPackingAlgorithm;
+use reipack::nn::{
+    evaluate_bins, evaluate_knapsack, evaluate_model, evaluate_packer, load_weights, store_weights,
+    train_model, TrainingParameters,
+};
 
-use geometry::Rect;
+use reipack::geometry::Spacing;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+
+    /// Seed for all of the randomness used, so that a run can be reproduced exactly. If not
+    /// given, a random seed is chosen and printed.
+    #[arg(long, global = true)]
+    seed: Option<u64>,
 }
 
 #[derive(Subcommand)]
@@ -56,12 +59,31 @@ enum Command {
         /// Number of samples.
         #[arg(short, long)]
         num_samples: usize,
+
+        /// Number of tree search simulations per rectangle. If not given, the network's favorite
+        /// position is used directly.
+        #[arg(short, long)]
+        simulations: Option<usize>,
+
+        /// Spread the rectangles of each sample over as few bins of the configured bounds as
+        /// possible, instead of requiring them to fit in one.
+        #[arg(short, long)]
+        bins: bool,
+
+        /// Place the most valuable subset of each sample's rectangles that fits, rejecting the
+        /// rest, instead of counting the sample as a failure.
+        #[arg(short, long, conflicts_with = "bins")]
+        knapsack: bool,
     },
 }
 
 fn main() -> Result<(), io::Error> {
     let cli = Cli::parse();
 
+    let seed = cli.seed.unwrap_or_else(rand::random);
+    eprintln!("Seed: {seed}");
+    let mut rng = StdRng::seed_from_u64(seed);
+
     match cli.command {
         Command::Train {
             config,
@@ -78,19 +100,17 @@ fn main() -> Result<(), io::Error> {
                 Ok(config) => config,
                 Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
             };
+            if let Err(err) = config.validate() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
 
-            let mut dimensions = vec![];
-            dimensions.push(config.bounds.area() as usize + 2);
-            dimensions.extend(config.hidden_layers.iter().copied());
-            dimensions.push(config.bounds.area() as usize);
-
-            let mut model = Model::new(&dimensions);
+            let mut model = Model::new(&config.dimensions());
 
             if let Some(in_weights) = in_weights {
                 load_weights(&mut model, &in_weights)?;
             }
 
-            train_model(&mut model, &config);
+            train_model(&mut model, &config, &mut rng);
 
             if let Some(out_weights) = out_weights {
                 store_weights(&model, &out_weights)?;
@@ -100,6 +120,9 @@ fn main() -> Result<(), io::Error> {
             config,
             in_weights,
             num_samples,
+            simulations,
+            bins,
+            knapsack,
         } => {
             let mut config_file_content = vec![];
             let mut config_file = File::open(config)?;
@@ -110,107 +133,78 @@ fn main() -> Result<(), io::Error> {
             let Ok(config) = toml::from_str::<TrainingParameters>(config_file_content) else {
                 return Err(io::Error::new(io::ErrorKind::InvalidData, "config isn't valid TOML?"))
             };
+            if let Err(err) = config.validate() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+            }
 
-            let mut dimensions = vec![];
-            dimensions.push(config.bounds.area() as usize + 2);
-            dimensions.extend(config.hidden_layers.iter().copied());
-            dimensions.push(config.bounds.area() as usize);
-
-            let mut model = Model::new(&dimensions);
+            let mut model = Model::new(&config.dimensions());
 
             load_weights(&mut model, &in_weights)?;
 
             let samples = std::iter::repeat_with(|| {
                 let packing_size =
-                    rand::thread_rng().gen_range(config.packing_size_min..=config.packing_size_max);
+                    rng.gen_range(config.packing_size_min..=config.packing_size_max);
 
-                std::iter::repeat_with(|| config.rects.choose(&mut rand::thread_rng()).unwrap())
+                std::iter::repeat_with(|| config.rects.choose(&mut rng).unwrap())
                     .take(packing_size)
+                    .collect::<Vec<_>>()
             })
-            .take(num_samples);
-
-            evaluate_model(&mut model.driver_mut(), &config.bounds, samples);
+            .take(num_samples)
+            .collect::<Vec<_>>();
+            let samples = samples.iter().map(|sample| sample.iter().copied());
+
+            if let (true, Some(simulations)) = (bins, simulations) {
+                let algorithm = BinPackingAlgorithm {
+                    bounds: config.bounds.clone(),
+                    packer: config.tree_search(&model, simulations),
+                    selection: BinSelection::BestFit,
+                    spacing: Spacing::default(),
+                };
+                evaluate_bins(&algorithm, &config.obstacles, &mut rng, samples);
+            } else if bins {
+                let algorithm = BinPackingAlgorithm {
+                    bounds: config.bounds.clone(),
+                    packer: NetworkPackingAlgorithm {
+                        model: &model,
+                        bounds: config.bounds.clone(),
+                        allow_rotation: config.allow_rotation,
+                        obstacles: config.obstacles.clone(),
+                        alignment: config.alignment,
+                    },
+                    selection: BinSelection::BestFit,
+                    spacing: Spacing::default(),
+                };
+                evaluate_bins(&algorithm, &config.obstacles, &mut rng, samples);
+            } else if let (true, Some(simulations)) = (knapsack, simulations) {
+                let algorithm = KnapsackPackingAlgorithm {
+                    bounds: config.bounds.clone(),
+                    packer: config.tree_search(&model, simulations),
+                    spacing: Spacing::default(),
+                };
+                evaluate_knapsack(&algorithm, &config.obstacles, &mut rng, samples);
+            } else if let Some(simulations) = simulations {
+                let algorithm = config.tree_search(&model, simulations);
+                evaluate_packer(
+                    &algorithm,
+                    &config.bounds,
+                    &config.obstacles,
+                    config.allow_rotation,
+                    &mut rng,
+                    samples,
+                );
+            } else {
+                evaluate_model(
+                    &mut model.driver_mut(),
+                    &config.bounds,
+                    &config.obstacles,
+                    config.allow_rotation,
+                    config.alignment,
+                    knapsack,
+                    samples,
+                );
+            }
         }
     }
 
     Ok(())
-
-    /*const RECTS_INIT: [Rect; 4] = [
-        Rect {
-            x1: 0,
-            x2: 10,
-            y1: 0,
-            y2: 10,
-        },
-        Rect {
-            x1: 0,
-            x2: 20,
-            y1: 0,
-            y2: 10,
-        },
-        Rect {
-            x1: 0,
-            x2: 10,
-            y1: 0,
-            y2: 20,
-        },
-        Rect {
-            x1: 0,
-            x2: 20,
-            y1: 0,
-            y2: 20,
-        },
-    ];
-
-    let trials = 10000000;
-    let mut output =
-        std::io::BufWriter::with_capacity(65536, std::fs::File::create("out.csv").unwrap());
-
-    let algorithm = algorithms::packing::random::RandomPackingAlgorithm;
-
-    let mut rects;
-    for _ in 0..trials {
-        while {
-            rects = RECTS_INIT;
-            !algorithm.pack(&mut rects)
-        } {}
-
-        let score_val = heuristics::score::score(&rects);
-        output.write_all(score_val.to_string().as_bytes()).unwrap();
-        output.write_all(b",").unwrap();
-        output
-            .write_all(
-                heuristics::spread::spread_score(&rects)
-                    .to_string()
-                    .as_bytes(),
-            )
-            .unwrap();
-        output.write_all(b",").unwrap();
-        output
-            .write_all(serialize_packing(&rects).as_bytes())
-            .unwrap();
-        output.write_all(b",").unwrap();
-        output
-            .write_all(serialize_order(&rects).as_bytes())
-            .unwrap();
-        output.write_all(b",").unwrap();
-        output
-            .write_all(
-                heuristics::closeness::closeness_score(&rects)
-                    .to_string()
-                    .as_bytes(),
-            )
-            .unwrap();
-        output.write_all(b"\n").unwrap();
-    }*/
-}
-
-fn serialize_packing(packing: &[Rect]) -> String {
-    BASE64_STANDARD.encode(serde_json::to_string(packing).unwrap())
-}
-
-fn serialize_order(packing: &[Rect]) -> String {
-    BASE64_STANDARD.encode(
-        serde_json::to_string(&packing.iter().map(|r| r.size()).collect::<Vec<_>>()).unwrap(),
-    )
 }
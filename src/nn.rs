@@ -1,5 +1,8 @@
+use base64::prelude::*;
 use neun::{AdamOptimizer, Model, ModelDriver, Optimizer, OptimizerInstance, SgdOptimizer};
-use rand::{seq::SliceRandom, Rng};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::io::{self, prelude::*, BufWriter};
@@ -7,7 +10,12 @@ use std::ops::Deref;
 use std::thread;
 use std::{fs::File, path::Path};
 
+use crate::algorithms::bins::identical::BinPackingAlgorithm;
+use crate::algorithms::knapsack::KnapsackPackingAlgorithm;
+use crate::algorithms::packing::mcts::{argmax, MctsPackingAlgorithm};
 use crate::geometry::Rect;
+use crate::heuristics::height::height_lower_bound;
+use crate::PackingAlgorithm;
 
 #[derive(Serialize, Deserialize)]
 pub struct TrainingParameters {
@@ -28,6 +36,69 @@ pub struct TrainingParameters {
     pub exploit_weight: f32,
     pub reward_threshold_start: f32,
     pub reward_threshold_end: f32,
+    /// Whether the network may place rectangles turned by 90 degrees. This doubles the number of
+    /// positions the network chooses between, so weights trained without it can't be reused.
+    #[serde(default)]
+    pub allow_rotation: bool,
+    /// Regions of `bounds` that are already used, which the network must place rectangles around.
+    #[serde(default)]
+    pub obstacles: Vec<Rect>,
+    /// The multiple that the coordinates of the positions the network chooses must be, with 1
+    /// allowing any position.
+    #[serde(default = "default_alignment")]
+    pub alignment: i32,
+    /// The number of tree search simulations used to place each rectangle during training. If
+    /// given, the network is trained towards the fraction of simulations that passed through each
+    /// position, as in AlphaZero, instead of towards its own packings that beat the reward
+    /// threshold.
+    #[serde(default)]
+    pub simulations: Option<usize>,
+}
+
+fn default_alignment() -> i32 {
+    1
+}
+
+impl TrainingParameters {
+    /// Returns the sizes of the layers of the network described by these parameters.
+    pub fn dimensions(&self) -> Vec<usize> {
+        let positions = position_count(&self.bounds, self.allow_rotation);
+
+        let mut dimensions = vec![];
+        dimensions.push(positions + 2);
+        dimensions.extend(self.hidden_layers.iter().copied());
+        dimensions.push(positions);
+        dimensions
+    }
+
+    /// Creates a tree search over these bounds, guided by `model`.
+    pub fn tree_search<'a>(
+        &self,
+        model: &'a Model,
+        simulations: usize,
+    ) -> MctsPackingAlgorithm<'a> {
+        MctsPackingAlgorithm {
+            model,
+            bounds: self.bounds.clone(),
+            simulations,
+            exploration: 1.5,
+            allow_rotation: self.allow_rotation,
+            obstacles: self.obstacles.clone(),
+            alignment: self.alignment,
+        }
+    }
+
+    /// Checks that every obstacle lies within the bounds, returning a description of the first
+    /// one that doesn't.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.obstacles.iter().find(|o| !self.bounds.contains(o)) {
+            Some(obstacle) => Err(format!(
+                "obstacle {obstacle:?} isn't within the bounds {:?}",
+                self.bounds
+            )),
+            None => Ok(()),
+        }
+    }
 }
 
 pub fn store_weights(model: &Model, path: &Path) -> io::Result<()> {
@@ -66,7 +137,19 @@ pub fn load_weights(model: &mut Model, path: &Path) -> io::Result<()> {
     Ok(())
 }
 
-pub fn train_model(model: &mut Model, params: &TrainingParameters) {
+/// The number of pieces each batch of training trials is split into to run in parallel.
+const BATCH_WORK_ITEMS: usize = 64;
+
+/// Trains `model` as described by `params`, taking all randomness from `rng`.
+///
+/// Each batch is split into the same number of pieces however many threads there are, and each
+/// piece is given its own generator seeded from `rng`. The pieces' gradients are summed in order,
+/// so training is reproducible given the same seed on any machine.
+///
+/// If `params` gives a number of simulations, each trial places its rectangles with a tree search
+/// guided by `model` and trains towards the search's policy. The improved network then guides the
+/// search of the next batch, which is the AlphaZero improvement loop.
+pub fn train_model(model: &mut Model, params: &TrainingParameters, rng: &mut dyn RngCore) {
     let parallelism = thread::available_parallelism()
         .map(|v| v.get())
         .unwrap_or(1);
@@ -75,6 +158,17 @@ pub fn train_model(model: &mut Model, params: &TrainingParameters) {
         .build()
         .unwrap();
 
+    train_model_on(&pool, model, params, rng);
+}
+
+/// Trains `model` like [train_model], running the pieces of each batch on `pool`. The size of the
+/// pool only changes how many pieces run at once, not the result.
+fn train_model_on(
+    pool: &rayon::ThreadPool,
+    model: &mut Model,
+    params: &TrainingParameters,
+    rng: &mut dyn RngCore,
+) {
     let mut dx = vec![0.0; model.variable_count()];
     let mut optimizer = AdamOptimizer {
         learning_rate: params.learning_rate_start,
@@ -97,7 +191,14 @@ pub fn train_model(model: &mut Model, params: &TrainingParameters) {
         // status message
         {
             let chosen_rect = &params.rects[params.rects.len() / 2];
-            let input = vectorize_input(&params.bounds, &[], chosen_rect.width(), chosen_rect.height());
+            let input = vectorize_input(
+                &params.bounds,
+                &params.obstacles,
+                chosen_rect.width(),
+                chosen_rect.height(),
+                params.allow_rotation,
+                params.alignment,
+            );
             let mut driver = model.driver();
             let result = driver.run(&input);
             println!(" input: {:?}", input);
@@ -115,101 +216,49 @@ pub fn train_model(model: &mut Model, params: &TrainingParameters) {
                 / prediction_validity.len() as f32,
         );
 
-        let trials_per_thread = params.batch_size / parallelism;
-        let results = pool.broadcast(|_ctx| {
-            let mut driver = model.driver();
-            let mut rng = rand::thread_rng();
-            let mut dx = vec![0.0; driver.model().variable_count()];
-            let mut prediction_validity = Vec::with_capacity(trials_per_thread);
-
-            let mut trial = 0;
-            while trial < trials_per_thread {
-                let (packing, net_actions) = loop {
-                    let packing_size =
-                        rng.gen_range(params.packing_size_min..=params.packing_size_max);
-
-                    let chosen_rects =
-                        std::iter::repeat_with(|| params.rects.choose(&mut rng).unwrap())
-                            .take(packing_size);
-
-                    if let Some(packing) = find_packing(
-                        &mut rand::thread_rng(),
-                        &mut driver,
-                        &params.bounds,
-                        chosen_rects,
+        // spread the batch over the pieces, with any remainder going to the first ones
+        let pieces = BATCH_WORK_ITEMS.min(params.batch_size.max(1));
+        let work = (0..pieces)
+            .map(|k| {
+                let trials =
+                    params.batch_size / pieces + usize::from(k < params.batch_size % pieces);
+                (trials, rng.gen::<u64>())
+            })
+            .collect::<Vec<_>>();
+        let results = pool.install(|| {
+            work.into_par_iter()
+                .map(|(trials, seed)| {
+                    train_trials(
+                        model,
+                        params,
+                        trials,
+                        seed,
                         exploit_chance,
-                    ) {
-                        break packing;
-                    }
-                };
-
-                let reward = reward(&params.bounds, &packing);
-
-                if reward > reward_threshold {
-                    trial += 1;
-
-                    let mut q = (reward - reward_threshold) / (1.0 - reward_threshold);
-
-                    for (i, rect) in packing.iter().enumerate().rev() {
-                        let packing_up_to = &packing[..i];
-
-                        let input = vectorize_input(
-                            &params.bounds,
-                            packing_up_to,
-                            rect.width(),
-                            rect.height(),
-                        );
-                        let target = vectorize_output(&params.bounds, rect.x1, rect.y1);
-
-                        let result = driver.run_and_record(&input);
-
-                        let (x1, y1) = devectorize_output(&params.bounds, result.output());
-                        let predicted = Rect {
-                            x1,
-                            y1,
-                            x2: x1 + rect.width(),
-                            y2: y1 + rect.height(),
-                        };
-                        let valid = packing_up_to.iter().all(|r| !r.overlaps(&predicted));
-                        prediction_validity.push(valid);
-
-                        let weight = if net_actions[i] {
-                            q * params.exploit_weight
-                        } else {
-                            q
-                        };
-                        result.compute_gradients(&target, |idx, val| dx[idx] += weight * val);
-
-                        q *= params.future_discount;
-                    }
-                }
-
-                trial += 1;
-            }
-
-            (dx, prediction_validity)
+                        reward_threshold,
+                    )
+                })
+                .collect::<Vec<_>>()
         });
 
-        for (thread_dx, thread_prediction_validity) in results {
+        for (piece_dx, piece_prediction_validity) in results {
             dx.iter_mut()
-                .zip(thread_dx.iter())
-                .for_each(|(dx, tdx)| *dx += tdx);
+                .zip(piece_dx.iter())
+                .for_each(|(dx, pdx)| *dx += pdx);
             if prediction_validity.capacity()
-                < prediction_validity.len() + thread_prediction_validity.len()
+                < prediction_validity.len() + piece_prediction_validity.len()
             {
-                for _ in 0..thread_prediction_validity.len() {
+                for _ in 0..piece_prediction_validity.len() {
                     prediction_validity.pop_front();
                 }
             }
-            for p in thread_prediction_validity {
+            for p in piece_prediction_validity {
                 prediction_validity.push_back(p);
             }
-
-            trial += trials_per_thread;
         }
+        trial += params.batch_size;
 
         dx.iter_mut()
-            .for_each(|dx| *dx /= (trials_per_thread * parallelism) as f32);
+            .for_each(|dx| *dx /= params.batch_size.max(1) as f32);
         optimizer.learning_rate = (1.0 - fraction_complete) * params.learning_rate_start
             + fraction_complete * params.learning_rate_end;
         optimizer.apply(model.variables_mut().zip(dx.iter()));
@@ -217,18 +266,220 @@ pub fn train_model(model: &mut Model, params: &TrainingParameters) {
     }
 }
 
+/// Runs `trials` training trials against `model` with a generator seeded by `seed`, returning
+/// the summed gradients and whether each of the network's predictions was valid.
+fn train_trials(
+    model: &Model,
+    params: &TrainingParameters,
+    trials: usize,
+    seed: u64,
+    exploit_chance: f32,
+    reward_threshold: f32,
+) -> (Vec<f32>, Vec<bool>) {
+    let mut driver = model.driver();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut dx = vec![0.0; driver.model().variable_count()];
+    let mut prediction_validity = Vec::with_capacity(trials);
+
+    let mut trial = 0;
+    while trial < trials {
+        if let Some(simulations) = params.simulations {
+            let packing_size = rng.gen_range(params.packing_size_min..=params.packing_size_max);
+            let chosen_rects =
+                std::iter::repeat_with(|| params.rects.choose(&mut rng).unwrap().clone())
+                    .take(packing_size)
+                    .collect::<Vec<_>>();
+
+            search_trial(
+                &params.tree_search(model, simulations),
+                &mut driver,
+                &mut rng,
+                chosen_rects,
+                exploit_chance,
+                &mut dx,
+                &mut prediction_validity,
+            );
+
+            trial += 1;
+            continue;
+        }
+
+        let (packing, net_actions, rotations) = loop {
+            let packing_size =
+                rng.gen_range(params.packing_size_min..=params.packing_size_max);
+
+            let chosen_rects =
+                std::iter::repeat_with(|| params.rects.choose(&mut rng).unwrap())
+                    .take(packing_size)
+                    .collect::<Vec<_>>();
+
+            if let Some(packing) = find_packing(
+                &mut rng,
+                &mut driver,
+                &params.bounds,
+                &params.obstacles,
+                chosen_rects.into_iter(),
+                exploit_chance,
+                params.allow_rotation,
+                params.alignment,
+            ) {
+                break packing;
+            }
+        };
+
+        let reward = reward(&params.bounds, &packing);
+
+        if reward > reward_threshold {
+            trial += 1;
+
+            let mut q = (reward - reward_threshold) / (1.0 - reward_threshold);
+
+            for (i, rect) in packing.iter().enumerate().rev() {
+                let packing_up_to = params
+                    .obstacles
+                    .iter()
+                    .chain(&packing[..i])
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                // the network was asked to place the rectangle as it was given
+                let (width, height) = if rotations[i] {
+                    (rect.height(), rect.width())
+                } else {
+                    (rect.width(), rect.height())
+                };
+
+                let input = vectorize_input(
+                    &params.bounds,
+                    &packing_up_to,
+                    width,
+                    height,
+                    params.allow_rotation,
+                    params.alignment,
+                );
+                let target = vectorize_output(
+                    &params.bounds,
+                    rect.x1,
+                    rect.y1,
+                    rotations[i],
+                    params.allow_rotation,
+                );
+
+                let result = driver.run_and_record(&input);
+
+                let predicted = devectorize_output(
+                    &params.bounds,
+                    result.output(),
+                    width,
+                    height,
+                    params.alignment,
+                );
+                let valid = packing_up_to.iter().all(|r| !r.overlaps(&predicted));
+                prediction_validity.push(valid);
+
+                let weight = if net_actions[i] {
+                    q * params.exploit_weight
+                } else {
+                    q
+                };
+                result.compute_gradients(&target, |idx, val| dx[idx] += weight * val);
+
+                q *= params.future_discount;
+            }
+        }
+
+        trial += 1;
+    }
+
+    (dx, prediction_validity)
+}
+
+/// Places `rects` one at a time with `search`, adding to `dx` the gradients that move the
+/// network's output for each placement towards the search's policy.
+///
+/// Each rectangle is placed at the most visited position with a chance of `exploit_chance`, and
+/// otherwise at a position chosen in proportion to its visits, so that later rectangles are
+/// searched from more than the network's favorite packings.
+fn search_trial(
+    search: &MctsPackingAlgorithm,
+    driver: &mut ModelDriver<impl Deref<Target = Model>>,
+    rng: &mut impl Rng,
+    mut rects: Vec<Rect>,
+    exploit_chance: f32,
+    dx: &mut [f32],
+    prediction_validity: &mut Vec<bool>,
+) {
+    for i in 0..rects.len() {
+        let policy = search.policy(driver, &rects[..i], &rects[i..]);
+
+        let exploit = rng.gen_range(0.0..1.0) < exploit_chance;
+        let action = if exploit {
+            argmax(&policy, |i| policy[i] > 0.0)
+        } else {
+            WeightedIndex::new(&policy).ok().map(|d| d.sample(rng))
+        };
+
+        // no position is available, so the search has nothing to teach
+        let Some(action) = action else { return };
+
+        let packing_up_to = search
+            .obstacles
+            .iter()
+            .chain(&rects[..i])
+            .cloned()
+            .collect::<Vec<_>>();
+        let (width, height) = (rects[i].width(), rects[i].height());
+
+        let input = vectorize_input(
+            &search.bounds,
+            &packing_up_to,
+            width,
+            height,
+            search.allow_rotation,
+            search.alignment,
+        );
+        let result = driver.run_and_record(&input);
+
+        let predicted = devectorize_output(
+            &search.bounds,
+            result.output(),
+            width,
+            height,
+            search.alignment,
+        );
+        let valid = packing_up_to.iter().all(|r| !r.overlaps(&predicted));
+        prediction_validity.push(valid);
+
+        result.compute_gradients(&policy, |idx, val| dx[idx] += val);
+
+        rects[i] = devectorize_position(&search.bounds, action, width, height);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn find_packing<'a>(
     rng: &mut impl rand::Rng,
     driver: &mut ModelDriver<impl Deref<Target = Model>>,
     bounds: &Rect,
+    obstacles: &[Rect],
     rects: impl Iterator<Item = &'a Rect>,
     exploit_chance: f32,
-) -> Option<(Vec<Rect>, Vec<bool>)> {
-    let mut packing = Vec::<Rect>::with_capacity(rects.size_hint().0);
+    allow_rotation: bool,
+    alignment: i32,
+) -> Option<(Vec<Rect>, Vec<bool>, Vec<bool>)> {
+    let mut packing = obstacles.to_vec();
     let mut net_choices = Vec::<bool>::with_capacity(rects.size_hint().0);
+    let mut rotations = Vec::<bool>::with_capacity(rects.size_hint().0);
 
     for rect in rects {
-        let input = vectorize_input(bounds, &packing, rect.width(), rect.height());
+        let input = vectorize_input(
+            bounds,
+            &packing,
+            rect.width(),
+            rect.height(),
+            allow_rotation,
+            alignment,
+        );
 
         let exploit = rng.gen_range(0.0..1.0) < exploit_chance;
 
@@ -239,6 +490,7 @@ fn find_packing<'a>(
                 .output()
                 .iter()
                 .enumerate()
+                .filter(|&(i, _)| is_aligned(bounds, i, alignment))
                 .reduce(|a, b| if a.1 > b.1 { a } else { b })
                 .map(|(i, _)| i)
         } else {
@@ -260,64 +512,163 @@ fn find_packing<'a>(
 
         let Some(chosen_pos_index) = chosen_pos_index else { return None };
 
-        let x1 = chosen_pos_index as i32 / bounds.height();
-        let y1 = chosen_pos_index as i32 % bounds.height();
-
-        let placed_rect = Rect {
-            x1,
-            y1,
-            x2: x1 + rect.width(),
-            y2: y1 + rect.height(),
-        };
+        let placed_rect =
+            devectorize_position(bounds, chosen_pos_index, rect.width(), rect.height());
 
         if bounds.contains(&placed_rect) && packing.iter().all(|r| !r.overlaps(&placed_rect)) {
             packing.push(placed_rect);
             net_choices.push(exploit);
+            rotations.push(chosen_pos_index >= bounds.area() as usize);
         } else {
             return None;
         }
     }
 
-    Some((packing, net_choices))
+    Some((packing.split_off(obstacles.len()), net_choices, rotations))
 }
 
 pub fn evaluate_model<'a>(
     driver: &mut ModelDriver<&mut Model>,
     bounds: &Rect,
+    obstacles: &[Rect],
+    allow_rotation: bool,
+    alignment: i32,
+    knapsack: bool,
     samples: impl Iterator<Item = impl Iterator<Item = &'a Rect>>,
 ) {
     let mut samples_count = 0usize;
     let mut fails_count = 0usize;
     let mut total_reward = 0.0;
+    let mut total_value = 0.0;
 
     for rects in samples {
-        let mut packing = Vec::with_capacity(rects.size_hint().0);
+        let items = rects.cloned().collect::<Vec<_>>();
+        let mut packing = obstacles.to_vec();
+        let mut rejected = vec![];
 
         let mut success = true;
-        for rect in rects {
-            let input = vectorize_input(bounds, &packing, rect.width(), rect.height());
-            let (x1, y1) = devectorize_output(bounds, driver.run(&input).output());
-            let rect = Rect {
-                x1,
-                y1,
-                x2: x1 + rect.width(),
-                y2: y1 + rect.height(),
-            };
+        for (i, rect) in items.iter().enumerate() {
+            let input = vectorize_input(
+                bounds,
+                &packing,
+                rect.width(),
+                rect.height(),
+                allow_rotation,
+                alignment,
+            );
+            let rect = devectorize_output(
+                bounds,
+                driver.run(&input).output(),
+                rect.width(),
+                rect.height(),
+                alignment,
+            );
 
             if bounds.contains(&rect) && packing.iter().all(|r| !r.overlaps(&rect)) {
                 packing.push(rect);
+            } else if knapsack {
+                // leave the rectangle out and carry on with the rest
+                rejected.push(i);
             } else {
-                println!("FAIL: {packing:?}, TRIED: {rect:?}");
+                println!("FAIL: {:?}, TRIED: {rect:?}", &packing[obstacles.len()..]);
                 success = false;
                 break;
             }
         }
 
+        let packing = packing.split_off(obstacles.len());
+
+        if knapsack {
+            total_value += packed_value(&items, &packing);
+        }
+
+        if success && rejected.is_empty() {
+            let rotated = Rect::rotations(&items, &packing);
+            println!(
+                "SUCCESS: {packing:?}, ROTATED: {rotated:?}, SERIALIZED: {}",
+                serialize_packing(&packing, obstacles)
+            );
+            samples_count += 1;
+            total_reward += reward(bounds, &packing);
+        } else if knapsack && !packing.is_empty() {
+            // a partial packing is still a valid solution in knapsack mode
+            println!(
+                "PARTIAL: {packing:?}, REJECTED: {rejected:?}, SERIALIZED: {}",
+                serialize_packing(&packing, obstacles)
+            );
+            samples_count += 1;
+            total_reward += reward(bounds, &packing);
+        } else {
+            if knapsack {
+                println!("FAIL: nothing fits, REJECTED: {rejected:?}");
+            }
+            fails_count += 1;
+        }
+    }
+
+    println!(
+        "Average reward on success: {}",
+        total_reward / samples_count as f32
+    );
+    println!(
+        "Success rate: {}%",
+        100.0 * samples_count as f32 / (samples_count + fails_count) as f32
+    );
+    if knapsack {
+        println!(
+            "Average packed value: {}%",
+            100.0 * total_value / (samples_count + fails_count) as f64
+        );
+    }
+}
+
+/// Like [evaluate_model], but places the rectangles of each sample using `algorithm`. The height
+/// each packing uses is reported against [height_lower_bound] for a strip as wide as `bounds`.
+pub fn evaluate_packer<'a>(
+    algorithm: &impl PackingAlgorithm,
+    bounds: &Rect,
+    obstacles: &[Rect],
+    allow_rotation: bool,
+    rng: &mut dyn RngCore,
+    samples: impl Iterator<Item = impl Iterator<Item = &'a Rect>>,
+) {
+    let mut samples_count = 0usize;
+    let mut fails_count = 0usize;
+    let mut total_reward = 0.0;
+    let mut total_height = 0usize;
+    let mut total_lower_bound = 0usize;
+
+    for rects in samples {
+        let items = rects.cloned().collect::<Vec<_>>();
+        let mut packing = items.clone();
+
+        let result = algorithm.pack(&mut packing, rng);
+        let success = result.is_ok()
+            && packing.iter().enumerate().all(|(i, rect)| {
+                bounds.contains(rect)
+                    && packing[..i].iter().chain(obstacles).all(|r| !r.overlaps(rect))
+            });
+
         if success {
-            println!("SUCCESS: {packing:?}");
+            let rotated = Rect::rotations(&items, &packing);
+            let used_height = packing.iter().map(|r| r.y2 - bounds.y1).max().unwrap_or(0);
+            // every rectangle fits within the bounds, so the strip is never too narrow for one
+            let lower_bound =
+                height_lower_bound(&items, bounds.width(), allow_rotation).unwrap_or(0);
+            println!(
+                "SUCCESS: {packing:?}, ROTATED: {rotated:?}, SERIALIZED: {}",
+                serialize_packing(&packing, obstacles)
+            );
+            println!("HEIGHT: {used_height} (lower bound {lower_bound})");
             samples_count += 1;
             total_reward += reward(bounds, &packing);
+            total_height += used_height as usize;
+            total_lower_bound += lower_bound as usize;
+        } else if let Err(err) = result {
+            println!("FAIL: {packing:?}, REASON: {err}");
+            fails_count += 1;
         } else {
+            println!("FAIL: {packing:?}");
             fails_count += 1;
         }
     }
@@ -326,21 +677,216 @@ pub fn evaluate_model<'a>(
         "Average reward on success: {}",
         total_reward / samples_count as f32
     );
+    println!(
+        "Average height on success: {} (lower bound {})",
+        total_height as f64 / samples_count as f64,
+        total_lower_bound as f64 / samples_count as f64
+    );
     println!(
         "Success rate: {}%",
         100.0 * samples_count as f32 / (samples_count + fails_count) as f32
     );
 }
 
-fn vectorize_input(
+/// Like [evaluate_model] in knapsack mode, but chooses and places the rectangles of each sample
+/// using `algorithm`, which places them around the `obstacles`.
+pub fn evaluate_knapsack<'a>(
+    algorithm: &KnapsackPackingAlgorithm<impl PackingAlgorithm>,
+    obstacles: &[Rect],
+    rng: &mut dyn RngCore,
+    samples: impl Iterator<Item = impl Iterator<Item = &'a Rect>>,
+) {
+    let mut samples_count = 0usize;
+    let mut fails_count = 0usize;
+    let mut total_reward = 0.0;
+    let mut total_value = 0.0;
+
+    for rects in samples {
+        let items = rects.cloned().collect::<Vec<_>>();
+        let packing = algorithm.pack_knapsack(&items, None, rng);
+
+        total_value += packed_value(&items, &packing.placements);
+
+        if packing.rejected.is_empty() {
+            let rotated = Rect::rotations(&items, &packing.placements);
+            println!(
+                "SUCCESS: {:?}, ROTATED: {rotated:?}, SERIALIZED: {}",
+                packing.placements,
+                serialize_packing(&packing.placements, obstacles)
+            );
+            samples_count += 1;
+            total_reward += reward(&algorithm.bounds, &packing.placements);
+        } else if !packing.placements.is_empty() {
+            println!(
+                "PARTIAL: {:?}, REJECTED: {:?}, SERIALIZED: {}",
+                packing.placements,
+                packing.rejected,
+                serialize_packing(&packing.placements, obstacles)
+            );
+            samples_count += 1;
+            total_reward += reward(&algorithm.bounds, &packing.placements);
+        } else {
+            println!("FAIL: nothing fits, REJECTED: {:?}", packing.rejected);
+            fails_count += 1;
+        }
+    }
+
+    println!(
+        "Average reward on success: {}",
+        total_reward / samples_count as f32
+    );
+    println!(
+        "Success rate: {}%",
+        100.0 * samples_count as f32 / (samples_count + fails_count) as f32
+    );
+    println!(
+        "Average packed value: {}%",
+        100.0 * total_value / (samples_count + fails_count) as f64
+    );
+}
+
+/// Encodes `packing` for the viewer, along with the `obstacles` if there are any.
+pub fn serialize_packing(packing: &[Rect], obstacles: &[Rect]) -> String {
+    let json = if obstacles.is_empty() {
+        serde_json::to_string(packing)
+    } else {
+        serde_json::to_string(&serde_json::json!({
+            "packing": packing,
+            "obstacles": obstacles,
+        }))
+    };
+
+    BASE64_STANDARD.encode(json.unwrap())
+}
+
+/// Returns the fraction of the total area of `items` that made it into `packing`.
+fn packed_value(items: &[Rect], packing: &[Rect]) -> f64 {
+    let total = items.iter().map(|r| r.area() as f64).sum::<f64>();
+    let packed = packing.iter().map(|r| r.area() as f64).sum::<f64>();
+
+    if total > 0.0 {
+        packed / total
+    } else {
+        1.0
+    }
+}
+
+/// Spreads the rectangles of each sample over as many bins as `algorithm` needs, reporting the
+/// number of bins used against the area lower bound. Every bin is serialized with the
+/// `obstacles`, which `algorithm` should place its rectangles around.
+pub fn evaluate_bins<'a>(
+    algorithm: &BinPackingAlgorithm<impl PackingAlgorithm>,
+    obstacles: &[Rect],
+    rng: &mut dyn RngCore,
+    samples: impl Iterator<Item = impl Iterator<Item = &'a Rect>>,
+) {
+    let mut samples_count = 0usize;
+    let mut fails_count = 0usize;
+    let mut total_bins = 0usize;
+    let mut total_lower_bound = 0usize;
+    let mut total_utilization = 0.0;
+
+    for rects in samples {
+        let items = rects.cloned().collect::<Vec<_>>();
+
+        if let Some(packing) = algorithm.pack_bins(&items, rng) {
+            let serialized = packing
+                .bins
+                .iter()
+                .map(|bin| serialize_packing(&bin.placements, obstacles))
+                .collect::<Vec<_>>();
+            println!(
+                "SUCCESS: {} bins (lower bound {}): {:?}, SERIALIZED: {serialized:?}",
+                packing.bins.len(),
+                packing.lower_bound,
+                packing.bins
+            );
+            samples_count += 1;
+            total_bins += packing.bins.len();
+            total_lower_bound += packing.lower_bound;
+            total_utilization += packing.utilization(&algorithm.bounds);
+        } else {
+            println!("FAIL: {items:?}");
+            fails_count += 1;
+        }
+    }
+
+    println!(
+        "Average bins on success: {} (lower bound {})",
+        total_bins as f64 / samples_count as f64,
+        total_lower_bound as f64 / samples_count as f64
+    );
+    println!(
+        "Average utilization on success: {}%",
+        100.0 * total_utilization / samples_count as f64
+    );
+    println!(
+        "Success rate: {}%",
+        100.0 * samples_count as f32 / (samples_count + fails_count) as f32
+    );
+}
+
+/// Returns the number of positions the network chooses between: every cell of `bounds`, for each
+/// orientation a rectangle may be placed in.
+pub fn position_count(bounds: &Rect, allow_rotation: bool) -> usize {
+    let orientations = if allow_rotation { 2 } else { 1 };
+    bounds.area() as usize * orientations
+}
+
+/// Builds the network's input for placing a `chosen_width` by `chosen_height` rectangle into
+/// `packing`: a 1 for each position the rectangle can be placed at, followed by the same for the
+/// rotated rectangle if `allow_rotation` is true, followed by the rectangle's relative size.
+///
+/// Positions whose coordinates aren't multiples of `alignment` are marked as blocked, so an
+/// alignment of 1 leaves every position available. Obstacles are given by including them in
+/// `packing`, which blocks them the same as placed rectangles.
+pub fn vectorize_input(
     bounds: &Rect,
     packing: &[Rect],
     chosen_width: i32,
     chosen_height: i32,
+    allow_rotation: bool,
+    alignment: i32,
 ) -> Vec<f32> {
-    let buf_len = bounds.area() as usize + 2;
-    let mut buf = vec![1.0; bounds.area() as usize + 2];
+    let area = bounds.area() as usize;
+    let buf_len = position_count(bounds, allow_rotation) + 2;
+    let mut buf = vec![1.0; buf_len];
+
+    mark_blocked(
+        &mut buf[..area],
+        bounds,
+        packing,
+        chosen_width,
+        chosen_height,
+        alignment,
+    );
+    if allow_rotation {
+        mark_blocked(
+            &mut buf[area..2 * area],
+            bounds,
+            packing,
+            chosen_height,
+            chosen_width,
+            alignment,
+        );
+    }
+
+    buf[buf_len - 2] = chosen_width as f32 / bounds.width() as f32;
+    buf[buf_len - 1] = chosen_height as f32 / bounds.height() as f32;
+
+    buf
+}
 
+/// Zeroes the entries of `buf` for the positions where a `chosen_width` by `chosen_height`
+/// rectangle would overlap `packing`, leave `bounds` or not be aligned to `alignment`.
+fn mark_blocked(
+    buf: &mut [f32],
+    bounds: &Rect,
+    packing: &[Rect],
+    chosen_width: i32,
+    chosen_height: i32,
+    alignment: i32,
+) {
     for rect in packing {
         for x in (rect.x1 - chosen_width + 1).max(0)..rect.x2 {
             for y in (rect.y1 - chosen_height + 1).max(0)..rect.y2 {
@@ -361,34 +907,78 @@ fn vectorize_input(
         }
     }
 
-    buf[buf_len - 2] = chosen_width as f32 / bounds.width() as f32;
-    buf[buf_len - 1] = chosen_height as f32 / bounds.height() as f32;
-
-    buf
+    for (i, value) in buf.iter_mut().enumerate() {
+        if !is_aligned(bounds, i, alignment) {
+            *value = 0.0;
+        }
+    }
 }
 
-fn vectorize_output(bounds: &Rect, chosen_x: i32, chosen_y: i32) -> Vec<f32> {
-    let mut buf = vec![0.0; bounds.area() as usize];
+fn vectorize_output(
+    bounds: &Rect,
+    chosen_x: i32,
+    chosen_y: i32,
+    rotated: bool,
+    allow_rotation: bool,
+) -> Vec<f32> {
+    let mut buf = vec![0.0; position_count(bounds, allow_rotation)];
 
-    buf[(bounds.height() * chosen_x) as usize + chosen_y as usize] = 1.0;
+    let offset = if rotated { bounds.area() as usize } else { 0 };
+    buf[offset + (bounds.height() * chosen_x) as usize + chosen_y as usize] = 1.0;
 
     buf
 }
 
-fn devectorize_output(bounds: &Rect, output: &[f32]) -> (i32, i32) {
+/// Places a `width` by `height` rectangle at the network's favorite position in `output` whose
+/// coordinates are multiples of `alignment`.
+fn devectorize_output(
+    bounds: &Rect,
+    output: &[f32],
+    width: i32,
+    height: i32,
+    alignment: i32,
+) -> Rect {
     let chosen_pos_index = output
         .iter()
         .enumerate()
+        .filter(|&(i, _)| is_aligned(bounds, i, alignment))
         .reduce(|a, b| if a.1 > b.1 { a } else { b })
         .unwrap()
         .0;
-    let chosen_x1 = chosen_pos_index as i32 / bounds.height();
-    let chosen_y1 = chosen_pos_index as i32 % bounds.height();
 
-    (chosen_x1, chosen_y1)
+    devectorize_position(bounds, chosen_pos_index, width, height)
+}
+
+/// Whether the position with the given index in the network's output, in either orientation, has
+/// coordinates that are multiples of `alignment`.
+pub fn is_aligned(bounds: &Rect, index: usize, alignment: i32) -> bool {
+    let index = index as i32 % bounds.area();
+    let (x, y) = (index / bounds.height(), index % bounds.height());
+    alignment <= 1 || (x % alignment == 0 && y % alignment == 0)
+}
+
+/// Places a `width` by `height` rectangle at the position with the given index in the network's
+/// output, turned by 90 degrees if the index is among the rotated positions.
+pub fn devectorize_position(bounds: &Rect, index: usize, width: i32, height: i32) -> Rect {
+    let area = bounds.area() as usize;
+    let (index, width, height) = if index >= area {
+        (index - area, height, width)
+    } else {
+        (index, width, height)
+    };
+
+    let x1 = index as i32 / bounds.height();
+    let y1 = index as i32 % bounds.height();
+
+    Rect {
+        x1,
+        y1,
+        x2: x1 + width,
+        y2: y1 + height,
+    }
 }
 
-fn reward(bounds: &Rect, rects: &[Rect]) -> f32 {
+pub fn reward(bounds: &Rect, rects: &[Rect]) -> f32 {
     let worst = bounds.area();
     let best = {
         let max_width = rects.iter().map(Rect::width).max().unwrap();
@@ -399,3 +989,79 @@ fn reward(bounds: &Rect, rects: &[Rect]) -> f32 {
 
     ((worst - actual) as f32 / (worst - best) as f32).powi(2)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obstacles_must_be_within_bounds() {
+        let mut config = toml::from_str::<TrainingParameters>(include_str!("../run.toml")).unwrap();
+        assert!(config.validate().is_ok());
+
+        config.obstacles = vec![Rect {
+            x1: 12,
+            y1: 12,
+            x2: 16,
+            y2: 16,
+        }];
+        assert!(config.validate().is_ok());
+
+        config.obstacles.push(Rect {
+            x1: 14,
+            y1: 0,
+            x2: 18,
+            y2: 2,
+        });
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn search_trials_train_towards_the_policy() {
+        let mut config = toml::from_str::<TrainingParameters>(include_str!("../run.toml")).unwrap();
+        config.hidden_layers = vec![16];
+        config.simulations = Some(4);
+        let model = Model::new(&config.dimensions());
+
+        let (dx, prediction_validity) = train_trials(&model, &config, 2, 0, 0.5, 0.0);
+
+        assert!(!prediction_validity.is_empty());
+        assert!(dx.iter().any(|&dx| dx != 0.0));
+        assert_eq!(
+            (dx, prediction_validity),
+            train_trials(&model, &config, 2, 0, 0.5, 0.0)
+        );
+    }
+
+    #[test]
+    fn training_does_not_depend_on_the_thread_count() {
+        let mut config = toml::from_str::<TrainingParameters>(include_str!("../run.toml")).unwrap();
+        config.hidden_layers = vec![16];
+        config.batch_size = 8;
+        config.trials = 16;
+        config.learning_rate_start = 0.01;
+
+        let start = Model::new(&config.dimensions());
+        let trained = [1, 4].map(|threads| {
+            let mut model = Model::new(&config.dimensions());
+            model
+                .variables_mut()
+                .zip(start.variables())
+                .for_each(|(v, s)| *v = *s);
+
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            train_model_on(&pool, &mut model, &config, &mut StdRng::seed_from_u64(0));
+
+            model.variables().copied().collect::<Vec<_>>()
+        });
+
+        assert!(trained[0]
+            .iter()
+            .zip(start.variables())
+            .any(|(t, s)| t != s));
+        assert_eq!(trained[0], trained[1]);
+    }
+}
@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use crate::geometry::Rect;
+
+/// A packing found by [PackingAlgorithm::pack](crate::PackingAlgorithm::pack), along with how it
+/// was found.
+#[derive(Debug, Clone)]
+pub struct PackingResult {
+    /// The packed rectangles, in the same order as they were left in the slice given to the
+    /// packer.
+    pub packing: Vec<Rect>,
+    /// The number of placements or whole packings the packer tried, which is only comparable
+    /// between runs of the same packer.
+    pub attempts: u64,
+    pub elapsed: Duration,
+    /// The [score](crate::heuristics::score::score) of the packing.
+    pub score: i32,
+}
+
+/// Why a packer didn't find a packing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackingError {
+    /// The rectangle with this index in the input couldn't be placed.
+    Unplaceable { item: usize },
+    /// The packer's budget ran out before it finished a packing.
+    BudgetExhausted,
+    /// The rectangles have more area in total than the space they're packed into.
+    InfeasibleByArea,
+    /// The packer left rectangles that match none of the input rectangles' sizes, so they can't be
+    /// traced back to the input.
+    Unmatched,
+}
+
+impl PackingError {
+    /// Puts `packing` back to the `input` it was packed from after a packer failed with this
+    /// error, returning the error with any rectangle identified by its index in the `input`
+    /// rather than in `packing`, or as [PackingError::Unmatched] if it can't be found there.
+    pub(crate) fn restoring(self, input: &[Rect], packing: &mut [Rect]) -> PackingError {
+        let err = match self {
+            PackingError::Unplaceable { item } => {
+                match Rect::match_items(input, packing).get(item) {
+                    Some(&item) if item != usize::MAX => PackingError::Unplaceable { item },
+                    _ => PackingError::Unmatched,
+                }
+            }
+            err => err,
+        };
+        packing.clone_from_slice(input);
+        err
+    }
+}
+
+impl fmt::Display for PackingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackingError::Unplaceable { item } => write!(f, "couldn't place rectangle {item}"),
+            PackingError::BudgetExhausted => write!(f, "ran out of budget"),
+            PackingError::InfeasibleByArea => write!(f, "rectangles have too much area to fit"),
+            PackingError::Unmatched => write!(f, "packing doesn't match the input rectangles"),
+        }
+    }
+}
+
+impl Error for PackingError {}
@@ -0,0 +1,229 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::geometry::Rect;
+
+/// Encodes the relative positions of a set of rectangles as a pair of permutations of their
+/// indices.
+///
+/// If `a` comes before `b` in both sequences, then `a` is left of `b`. If `a` comes after `b` in
+/// [SequencePair::positive] but before it in [SequencePair::negative], then `a` is before `b` on
+/// the y axis. Every packing can be represented this way.
+#[derive(Debug, Clone)]
+pub struct SequencePair {
+    pub positive: Vec<usize>,
+    pub negative: Vec<usize>,
+}
+
+/// A small change to a [SequencePair]. Applying the same swap twice undoes it.
+#[derive(Debug, Clone, Copy)]
+pub enum Swap {
+    /// Swaps two entries of the positive sequence.
+    Positive(usize, usize),
+    /// Swaps two entries of the negative sequence.
+    Negative(usize, usize),
+    /// Swaps two rectangles in both sequences, exchanging their places in the packing.
+    Both(usize, usize),
+}
+
+impl SequencePair {
+    /// Creates a sequence pair that places `n` rectangles in a single row.
+    pub fn row(n: usize) -> SequencePair {
+        SequencePair {
+            positive: (0..n).collect(),
+            negative: (0..n).collect(),
+        }
+    }
+
+    /// Creates a sequence pair that stacks `n` rectangles in a single column.
+    pub fn column(n: usize) -> SequencePair {
+        SequencePair {
+            positive: (0..n).rev().collect(),
+            negative: (0..n).collect(),
+        }
+    }
+
+    /// Creates a random sequence pair for `n` rectangles.
+    pub fn random(n: usize, rng: &mut (impl Rng + ?Sized)) -> SequencePair {
+        let mut pair = SequencePair::row(n);
+        pair.positive.shuffle(rng);
+        pair.negative.shuffle(rng);
+        pair
+    }
+
+    /// Returns the number of rectangles in this sequence pair.
+    pub fn len(&self) -> usize {
+        self.positive.len()
+    }
+
+    /// Returns true if this sequence pair has no rectangles.
+    pub fn is_empty(&self) -> bool {
+        self.positive.is_empty()
+    }
+
+    /// Applies `swap` to this sequence pair.
+    pub fn apply(&mut self, swap: Swap) {
+        match swap {
+            Swap::Positive(i, j) => self.positive.swap(i, j),
+            Swap::Negative(i, j) => self.negative.swap(i, j),
+            Swap::Both(a, b) => {
+                for sequence in [&mut self.positive, &mut self.negative] {
+                    for entry in sequence.iter_mut() {
+                        if *entry == a {
+                            *entry = b;
+                        } else if *entry == b {
+                            *entry = a;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Chooses a random swap. There must be at least two rectangles.
+    pub fn random_swap(&self, rng: &mut (impl Rng + ?Sized)) -> Swap {
+        let i = rng.gen_range(0..self.len());
+        let j = (i + rng.gen_range(1..self.len())) % self.len();
+
+        match rng.gen_range(0..3) {
+            0 => Swap::Positive(i, j),
+            1 => Swap::Negative(i, j),
+            _ => Swap::Both(i, j),
+        }
+    }
+
+    /// Moves each of the `rects` to the position this sequence pair gives it, packed as tightly
+    /// towards the origin as the sequence pair allows.
+    ///
+    /// This takes `O(n log n)` time.
+    pub fn decode(&self, rects: &mut [Rect]) {
+        let n = self.len();
+
+        let mut negative_index = vec![0; n];
+        for (i, &r) in self.negative.iter().enumerate() {
+            negative_index[r] = i;
+        }
+
+        // a rectangle's x is the furthest right edge among the rectangles before it in both
+        // sequences, which is a prefix maximum over the negative sequence
+        let mut xs = vec![0; n];
+        let mut tree = MaxTree::new(n);
+        for &r in self.positive.iter() {
+            xs[r] = tree.max_before(negative_index[r]);
+            tree.update(negative_index[r], xs[r] + rects[r].width());
+        }
+
+        let mut ys = vec![0; n];
+        let mut tree = MaxTree::new(n);
+        for &r in self.positive.iter().rev() {
+            ys[r] = tree.max_before(negative_index[r]);
+            tree.update(negative_index[r], ys[r] + rects[r].height());
+        }
+
+        for (r, rect) in rects.iter_mut().enumerate() {
+            *rect = Rect {
+                x1: xs[r],
+                y1: ys[r],
+                x2: xs[r] + rect.width(),
+                y2: ys[r] + rect.height(),
+            };
+        }
+    }
+}
+
+/// A Fenwick tree over prefix maximums.
+struct MaxTree {
+    values: Vec<i32>,
+}
+
+impl MaxTree {
+    fn new(n: usize) -> MaxTree {
+        MaxTree {
+            values: vec![0; n + 1],
+        }
+    }
+
+    /// Raises the value at `index` to at least `value`.
+    fn update(&mut self, index: usize, value: i32) {
+        let mut i = index + 1;
+        while i < self.values.len() {
+            self.values[i] = self.values[i].max(value);
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Returns the maximum value at the indices before `index`, or zero if there are none.
+    fn max_before(&self, index: usize) -> i32 {
+        let mut acc = 0;
+        let mut i = index;
+        while i > 0 {
+            acc = acc.max(self.values[i]);
+            i -= i & i.wrapping_neg();
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(width: i32, height: i32) -> Rect {
+        Rect {
+            x1: 0,
+            y1: 0,
+            x2: width,
+            y2: height,
+        }
+    }
+
+    #[test]
+    fn decode_follows_the_sequences() {
+        let pair = SequencePair {
+            positive: vec![0, 2, 1],
+            negative: vec![0, 1, 2],
+        };
+        let mut rects = vec![rect(2, 2), rect(3, 1), rect(1, 4)];
+        pair.decode(&mut rects);
+
+        // 0 is left of both others, and 1 is below 2
+        assert_eq!(
+            rects,
+            vec![
+                Rect {
+                    x1: 0,
+                    y1: 0,
+                    x2: 2,
+                    y2: 2,
+                },
+                Rect {
+                    x1: 2,
+                    y1: 0,
+                    x2: 5,
+                    y2: 1,
+                },
+                Rect {
+                    x1: 2,
+                    y1: 1,
+                    x2: 3,
+                    y2: 5,
+                },
+            ]
+        );
+
+        let position = |sequence: &[usize], r: usize| sequence.iter().position(|&s| s == r);
+        for a in 0..rects.len() {
+            for b in 0..rects.len() {
+                assert!(a == b || !rects[a].overlaps(&rects[b]));
+
+                let before_positive = position(&pair.positive, a) < position(&pair.positive, b);
+                let before_negative = position(&pair.negative, a) < position(&pair.negative, b);
+                if before_positive && before_negative {
+                    assert!(rects[a].x2 <= rects[b].x1, "{a} isn't left of {b}");
+                } else if !before_positive && before_negative {
+                    assert!(rects[a].y2 <= rects[b].y1, "{a} isn't below {b}");
+                }
+            }
+        }
+    }
+}
@@ -1,10 +1,123 @@
-use crate::geometry::Rect;
+use std::cmp::Ordering;
+use std::time::Instant;
+
+use rand::RngCore;
+
+use crate::algorithms::anytime::{AnytimePacking, Budget};
+use crate::geometry::{Rect, Size};
+use crate::heuristics::score::score;
+use crate::{PackingError, PackingResult};
 
 pub trait PackingAlgorithm {
-    /// Attempts to find a valid packing using the given rectangles.
+    /// Attempts to find a valid packing using the given rectangles, leaving it in `packing`.
+    ///
+    /// Any randomness comes from `rng`, so the same packing is found again given an `rng` in the
+    /// same state.
+    ///
+    /// Returns the number of attempts made if a packing was found. Otherwise, `packing` may be
+    /// left in any state, and a rectangle that couldn't be placed is identified by its index in
+    /// `packing` as it was left. Use [PackingAlgorithm::pack] to have the input restored instead.
+    fn try_pack(&self, packing: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError>;
+
+    /// Like [PackingAlgorithm::try_pack], but returns the packing along with how it was found,
+    /// and leaves `packing` unchanged on failure, with rectangles identified by their index in the
+    /// input.
+    fn pack(
+        &self,
+        packing: &mut [Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<PackingResult, PackingError> {
+        let start = Instant::now();
+        let input = packing.to_vec();
+
+        match self.try_pack(packing, rng) {
+            Ok(attempts) => Ok(PackingResult {
+                packing: packing.to_vec(),
+                attempts,
+                elapsed: start.elapsed(),
+                score: score(packing),
+            }),
+            Err(err) => Err(err.restoring(&input, packing)),
+        }
+    }
+
+    /// Like [PackingAlgorithm::pack], but puts each placement back at the index of the rectangle
+    /// it was packed from, as matched by [Rect::match_items], instead of in the order the packer
+    /// left them. This lets callers keep data alongside each rectangle.
+    ///
+    /// Returns the number of attempts made, and leaves `packing` unchanged on failure, including
+    /// failing with [PackingError::Unmatched] if a placement can't be matched to a rectangle.
+    fn pack_in_order(
+        &self,
+        packing: &mut [Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        let result = self.pack(&mut packing.to_vec(), rng)?;
+
+        let matches = Rect::match_items(packing, &result.packing);
+        if matches.contains(&usize::MAX) {
+            return Err(PackingError::Unmatched);
+        }
+
+        for (rect, i) in result.packing.iter().zip(matches) {
+            packing[i] = rect.clone();
+        }
+
+        Ok(result.attempts)
+    }
+}
+
+pub trait AnytimePackingAlgorithm: PackingAlgorithm {
+    type Score;
+
+    /// Searches for a packing of the given rectangles until it's done or `budget` runs out,
+    /// leaving the best packing found so far in `packing`.
+    ///
+    /// Returns how the search went if a packing was found. Otherwise, `packing` is left unchanged
+    /// and a rectangle that couldn't be placed is identified by its index in the input.
+    fn pack_anytime(
+        &self,
+        packing: &mut [Rect],
+        budget: &Budget,
+        rng: &mut dyn RngCore,
+    ) -> Result<AnytimePacking<Self::Score>, PackingError>;
+}
+
+pub trait ObstaclePackingAlgorithm: PackingAlgorithm {
+    /// Like [PackingAlgorithm::try_pack], but starts from a container where the fixed `obstacles`
+    /// are already in place, so that no rectangle overlaps them.
+    fn try_pack_around(
+        &self,
+        packing: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError>;
+}
+
+impl<P: PackingAlgorithm + ?Sized> PackingAlgorithm for &P {
+    fn try_pack(&self, packing: &mut [Rect], rng: &mut dyn RngCore) -> Result<u64, PackingError> {
+        (**self).try_pack(packing, rng)
+    }
+}
+
+impl<P: ObstaclePackingAlgorithm + ?Sized> ObstaclePackingAlgorithm for &P {
+    fn try_pack_around(
+        &self,
+        packing: &mut [Rect],
+        obstacles: &[Rect],
+        rng: &mut dyn RngCore,
+    ) -> Result<u64, PackingError> {
+        (**self).try_pack_around(packing, obstacles, rng)
+    }
+}
+
+pub trait OnlinePacker {
+    /// Places a rectangle of the given `size` without knowing which rectangles come next. The
+    /// placement is final: it is never moved to make room for later rectangles.
     ///
-    /// If `true` is returned, then a packing was found; otherwise, `false` is returned.
-    fn pack(&self, packing: &mut [Rect]) -> bool;
+    /// Returns where the rectangle was placed, or `None` if it can't be placed, in which case the
+    /// packer is left unchanged.
+    fn place(&mut self, size: &Size) -> Option<Rect>;
 }
 
 pub trait RectChoiceAlgorithm {
@@ -12,7 +125,7 @@ pub trait RectChoiceAlgorithm {
     /// the given partial `packing`.
     ///
     /// The result is implementation-defined if `choices` is empty.
-    fn choose(&self, packing: &[Rect], choices: &[Rect]) -> usize;
+    fn choose(&self, packing: &[Rect], choices: &[Rect], rng: &mut dyn RngCore) -> usize;
 
     /// Returns `true` if this choice algorithm is hinted to be nondeterministic.
     fn nondeterministic_hint(&self) -> bool {
@@ -31,8 +144,204 @@ pub trait PackingHeuristicScore<H: PackingHeuristic + ?Sized> {
     /// Returns true if `self` is a better score than `other`.
     fn is_better_than(&self, other: &Self) -> bool;
 
+    /// Returns how much worse `self` is than `other`. The result is negative if `self` is better.
+    fn worse_by(&self, other: &Self) -> f64;
+
+    /// Orders `self` before `other` if it is better, for sorting scores from best to worst.
+    fn cmp_better(&self, other: &Self) -> Ordering {
+        if self.is_better_than(other) {
+            Ordering::Less
+        } else if other.is_better_than(self) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+
     /// Returns the index of the best score. If `scores` is empty, returns `None`.
     fn best(scores: &[Self]) -> Option<usize>
     where
         Self: Sized;
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::algorithms::choosing::height::MaxHeightRectChoiceAlgorithm;
+    use crate::algorithms::packing::annealing::{AnnealingPackingAlgorithm, CoolingSchedule};
+    use crate::algorithms::packing::genetic::{Crossover, GeneticPackingAlgorithm};
+    use crate::algorithms::packing::guillotine::{
+        GuillotineFit, GuillotinePackingAlgorithm, GuillotineSplit,
+    };
+    use crate::algorithms::packing::iterative_random::IterativeRandomPackingAlgorithm;
+    use crate::algorithms::packing::maxrects::{MaxRectsPackingAlgorithm, MaxRectsRule};
+    use crate::algorithms::packing::random::RandomPackingAlgorithm;
+    use crate::algorithms::packing::shelf::{ShelfFit, ShelfPackingAlgorithm};
+    use crate::algorithms::packing::skyline::{SkylinePackingAlgorithm, SkylineRule};
+    use crate::algorithms::portfolio::{PortfolioMember, PortfolioPackingAlgorithm};
+    use crate::heuristics::score::ScorePackingHeuristic;
+
+    #[test]
+    fn packs_empty_input() {
+        let algorithms: [&dyn PackingAlgorithm; 4] = [
+            &SkylinePackingAlgorithm {
+                width: None,
+                rule: SkylineRule::BottomLeft,
+                allow_rotation: false,
+            },
+            &MaxRectsPackingAlgorithm {
+                width: None,
+                rule: MaxRectsRule::BestShortSideFit,
+                allow_rotation: false,
+            },
+            &GuillotinePackingAlgorithm {
+                width: None,
+                fit: GuillotineFit::BestArea,
+                split: GuillotineSplit::ShorterLeftoverAxis,
+                merge: true,
+                allow_rotation: false,
+            },
+            &ShelfPackingAlgorithm {
+                width: None,
+                choice: MaxHeightRectChoiceAlgorithm {},
+                fit: ShelfFit::First,
+                allow_rotation: false,
+            },
+        ];
+
+        for algorithm in algorithms {
+            let result = algorithm
+                .pack(&mut [], &mut StdRng::seed_from_u64(0))
+                .unwrap();
+            assert!(result.packing.is_empty());
+            assert_eq!(result.score, 0);
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_identical_packings() {
+        let random = || RandomPackingAlgorithm {
+            width: None,
+            allow_rotation: true,
+        };
+        let algorithms: Vec<Box<dyn PackingAlgorithm + Send + Sync>> = vec![
+            Box::new(random()),
+            Box::new(IterativeRandomPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                trials_per_iteration: 20,
+                width: None,
+                allow_rotation: true,
+            }),
+            Box::new(AnnealingPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                width: None,
+                iterations: 500,
+                schedule: CoolingSchedule::Geometric {
+                    initial: 20.0,
+                    factor: 0.99,
+                },
+                allow_rotation: true,
+            }),
+            Box::new(GeneticPackingAlgorithm {
+                heuristic: ScorePackingHeuristic,
+                width: None,
+                population_size: 10,
+                generations: 10,
+                crossover: Crossover::Order,
+                mutation_rate: 0.3,
+                elitism: 1,
+                tournament_size: 3,
+                allow_rotation: true,
+            }),
+            Box::new(PortfolioPackingAlgorithm {
+                members: vec![PortfolioMember {
+                    name: "random".to_owned(),
+                    packer: Box::new(random()),
+                    runs: 8,
+                }],
+                heuristic: ScorePackingHeuristic,
+            }),
+        ];
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let rects = (0..12)
+            .map(|_| Rect {
+                x1: 0,
+                y1: 0,
+                x2: rng.gen_range(1..=10),
+                y2: rng.gen_range(1..=10),
+            })
+            .collect::<Vec<_>>();
+
+        for (i, algorithm) in algorithms.iter().enumerate() {
+            let [a, b, c] = [0, 0, 1].map(|seed| {
+                let mut packing = rects.clone();
+                algorithm
+                    .pack(&mut packing, &mut StdRng::seed_from_u64(seed))
+                    .unwrap();
+                packing
+            });
+
+            assert_eq!(a, b, "algorithm {i}");
+            // a different seed finds a different packing, so the seed is really used
+            assert_ne!(a, c, "algorithm {i}");
+        }
+    }
+
+    /// Shrinks every rectangle to nothing, then fails on the rectangle at `item`.
+    struct ShrinkingPackingAlgorithm {
+        item: usize,
+    }
+
+    impl PackingAlgorithm for ShrinkingPackingAlgorithm {
+        fn try_pack(
+            &self,
+            rects: &mut [Rect],
+            _rng: &mut dyn RngCore,
+        ) -> Result<u64, PackingError> {
+            rects.iter_mut().for_each(|r| *r = Rect::ZERO);
+            Err(PackingError::Unplaceable { item: self.item })
+        }
+    }
+
+    #[test]
+    fn failed_packings_restore_the_input() {
+        let sizes = [(2, 2), (1, 1), (5, 1), (2, 1)];
+        let input = sizes
+            .iter()
+            .map(|&(w, h)| Rect {
+                x1: 0,
+                y1: 0,
+                x2: w,
+                y2: h,
+            })
+            .collect::<Vec<_>>();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // the skyline places the first rectangles before running into the one wider than it
+        let skyline = SkylinePackingAlgorithm {
+            width: Some(3),
+            rule: SkylineRule::BottomLeft,
+            allow_rotation: false,
+        };
+        let mut rects = input.clone();
+        let result = skyline.pack(&mut rects, &mut rng);
+        assert_eq!(result.err(), Some(PackingError::Unplaceable { item: 2 }));
+        assert_eq!(rects, input);
+
+        let result = skyline.pack_in_order(&mut rects, &mut rng);
+        assert_eq!(result, Err(PackingError::Unplaceable { item: 2 }));
+        assert_eq!(rects, input);
+
+        // rectangles that no longer match the input can't identify the one that failed
+        for item in [0, 3, 4, usize::MAX] {
+            let mut rects = input.clone();
+            let result = ShrinkingPackingAlgorithm { item }.pack(&mut rects, &mut rng);
+            assert_eq!(result.err(), Some(PackingError::Unmatched));
+            assert_eq!(rects, input);
+        }
+    }
+}